@@ -89,7 +89,7 @@ in
     in ''
       patchelf --set-rpath "${libPath}" "$out/bin/oxipaste"
       patchelf --set-rpath "${libPath}" "$out/bin/oxipaste_daemon"
-      patchelf --set-rpath "${libPath}" "$out/bin/oxipaste_command_runner"
+      patchelf --set-rpath "${libPath}" "$out/bin/oxipastectl"
     '';
 
     meta = with lib; {
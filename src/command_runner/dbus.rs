@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use zbus::proxy;
+use zbus::zvariant::{Fd, Type, Value};
+
+/// index, mimetype, size in bytes, text preview, pinned, created and last copied unix timestamps
+pub type Entry = (u32, String, u64, String, bool, u64, u64);
+
+/// name, mimetype, size in bytes, text preview and the unix timestamp of storing it
+pub type RegisterSummary = (String, String, u64, String, u64);
+
+/// Timestamps are unix seconds, 0 if it never happened.
+#[derive(Debug, Serialize, Deserialize, Type)]
+pub struct Diagnostics {
+    pub version: String,
+    pub backend: String,
+    pub watcher_running: bool,
+    pub watcher_state: String,
+    pub watcher_detail: String,
+    pub watcher_restarts: u32,
+    pub paused: bool,
+    pub collecting: bool,
+    pub config_path: String,
+    pub history_path: String,
+    pub history_file_size: u64,
+    pub entries: u32,
+    pub pinned: u32,
+    pub memory_usage: u64,
+    pub last_capture: u64,
+    pub last_error: String,
+    pub last_error_time: u64,
+}
+
+#[proxy(
+    interface = "org.Xetibo.OxiPasteDaemon",
+    default_service = "org.Xetibo.OxiPasteDaemon",
+    default_path = "/org/Xetibo/OxiPasteDaemon"
+)]
+#[allow(non_snake_case)]
+pub trait OxiPasteDbus {
+    async fn Copy(&self) -> zbus::Result<()>;
+    async fn AddItem(
+        &self,
+        content: &[u8],
+        mimetype: &str,
+        set_as_clipboard: bool,
+    ) -> zbus::Result<()>;
+    async fn GetEntries(&self) -> zbus::Result<Vec<Entry>>;
+    async fn SearchEntries(&self, query: &str) -> zbus::Result<Vec<Entry>>;
+    async fn GetEntry(&self, index: u32) -> zbus::Result<Entry>;
+    async fn GetContent(&self, index: u32) -> zbus::Result<(Vec<u8>, String)>;
+    async fn GetContentAs(&self, index: u32, mimetype: &str) -> zbus::Result<Vec<u8>>;
+    async fn Paste(&self, index: u32) -> zbus::Result<()>;
+    async fn PasteWithOptions(
+        &self,
+        index: u32,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<()>;
+    async fn DeleteAtIndex(&self, index: u32) -> zbus::Result<()>;
+    async fn DeleteAll(&self) -> zbus::Result<()>;
+    async fn DeleteAllForced(&self) -> zbus::Result<()>;
+    async fn RestorePrevious(&self) -> zbus::Result<u32>;
+    async fn Pin(&self, index: u32) -> zbus::Result<()>;
+    async fn Unpin(&self, index: u32) -> zbus::Result<()>;
+    async fn SetBurnAfterReading(&self, index: u32, burn: bool) -> zbus::Result<()>;
+    async fn Export(&self, fd: Fd<'_>) -> zbus::Result<()>;
+    async fn Import(&self, fd: Fd<'_>) -> zbus::Result<u32>;
+    async fn ImportReplace(&self, fd: Fd<'_>) -> zbus::Result<u32>;
+    async fn GetDiagnostics(&self) -> zbus::Result<Diagnostics>;
+    async fn Pause(&self) -> zbus::Result<()>;
+    async fn Resume(&self) -> zbus::Result<()>;
+    async fn StartCollecting(&self) -> zbus::Result<()>;
+    async fn StopCollecting(&self) -> zbus::Result<()>;
+    async fn StoreRegister(&self, name: &str, index: u32) -> zbus::Result<()>;
+    async fn StoreClipboardRegister(&self, name: &str) -> zbus::Result<()>;
+    async fn PasteRegister(&self, name: &str) -> zbus::Result<()>;
+    async fn GetRegisters(&self) -> zbus::Result<Vec<RegisterSummary>>;
+    async fn GetRegisterContent(&self, name: &str) -> zbus::Result<(Vec<u8>, String)>;
+    async fn DeleteRegister(&self, name: &str) -> zbus::Result<()>;
+    async fn QueueAdd(&self, indexes: &[u32]) -> zbus::Result<()>;
+    async fn QueueCollect(&self, count: u32) -> zbus::Result<()>;
+    async fn GetQueue(&self) -> zbus::Result<Vec<u32>>;
+    async fn ClearQueue(&self) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn item_added(&self, index: u32, mimetype: String, preview: String) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn item_removed(&self, index: u32, mimetype: String, preview: String) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn item_changed(&self, index: u32) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn item_moved(&self, index: u32, new_position: u32) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn history_cleared(&self) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn paused_changed(&self, paused: bool) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn collecting_changed(&self, collecting: bool) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn register_changed(&self, name: String) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn queue_changed(&self, remaining: u32) -> zbus::Result<()>;
+}
@@ -1,19 +1,514 @@
-use zbus::{proxy, Connection};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-#[proxy(
-    interface = "org.Xetibo.OxiPasteDaemon",
-    default_service = "org.Xetibo.OxiPasteDaemon",
-    default_path = "/org/Xetibo/OxiPasteDaemon"
-)]
-#[allow(non_snake_case)]
-trait OxiPasteDbus {
-    async fn Copy(&self) -> zbus::Result<()>;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use dbus::{Entry, OxiPasteDbusProxy};
+use futures_util::StreamExt;
+use output::{Format, WatchEvent};
+use zbus::Connection;
+use zbus::zvariant::{Fd, Value};
+
+mod dbus;
+mod output;
+
+const DAEMON_ERROR_PREFIX: &str = "org.Xetibo.OxiPasteDaemon.Error.";
+
+// clap itself exits with 2 on invalid usage
+const EXIT_FAILURE: u8 = 1;
+const EXIT_DAEMON_UNAVAILABLE: u8 = 3;
+const EXIT_INVALID_ID: u8 = 4;
+
+/// Control the OxiPaste clipboard history.
+/// IDs are positions in the history, 0 is the latest entry.
+#[derive(Parser)]
+#[command(name = "oxipastectl", version, about, arg_required_else_help = true)]
+struct Cli {
+    /// Output format of list, search, get --meta, watch and status
+    #[arg(long, value_enum, global = true, default_value_t = Format::Table)]
+    format: Format,
+    #[command(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Adds the current clipboard to the history, the wl-paste watcher calls this
+    Copy,
+    /// Adds the content of stdin as a new entry
+    Add {
+        /// Mimetype of the content
+        #[arg(long, default_value = "text/plain")]
+        mime: String,
+        /// Sets the new entry as the current clipboard as well
+        #[arg(long)]
+        paste: bool,
+    },
+    /// Lists all entries
+    List {
+        /// Includes the base64 encoded content, requires --format json
+        #[arg(long)]
+        content: bool,
+    },
+    /// Writes the content of an entry to stdout
+    Get {
+        id: u32,
+        /// Prints the metadata of the entry instead
+        #[arg(long)]
+        meta: bool,
+        /// Writes the content as this mimetype instead, parameters like charset are ignored.
+        /// Besides the mimetype an entry was copied as, the other mimetypes offered along with it
+        /// are stored as well, nothing is converted.
+        #[arg(long, conflicts_with = "meta")]
+        mime: Option<String>,
+        /// Includes the base64 encoded content, requires --format json
+        #[arg(long, requires = "meta")]
+        content: bool,
+    },
+    /// Sets an entry as the current clipboard
+    Paste {
+        id: u32,
+        /// Keeps a trailing newline, regardless of trim_newline in the config
+        #[arg(long, conflicts_with = "trim")]
+        no_trim: bool,
+        /// Removes a trailing newline, regardless of trim_newline in the config
+        #[arg(long)]
+        trim: bool,
+        /// Clears the clipboard after the first paste
+        #[arg(long)]
+        once: bool,
+        /// Waits until the entry was pasted or the clipboard was replaced
+        #[arg(long)]
+        foreground: bool,
+    },
+    /// Removes an entry
+    Delete { id: u32 },
+    /// Removes all entries except pinned ones
+    Clear {
+        /// Removes pinned entries as well
+        #[arg(long)]
+        force: bool,
+    },
+    /// Undoes the last deletion shortly after it happened, otherwise pastes the previous entry
+    #[command(alias = "undo")]
+    Restore,
+    /// Lists entries containing the query
+    Search {
+        query: String,
+        /// Includes the base64 encoded content, requires --format json
+        #[arg(long)]
+        content: bool,
+    },
+    /// Keeps an entry permanently
+    Pin { id: u32 },
+    /// Allows an entry to be evicted again
+    Unpin { id: u32 },
+    /// Removes an entry on its next paste and clears the clipboard after that paste
+    Burn {
+        id: u32,
+        /// Keeps the entry after pasting again
+        #[arg(long)]
+        off: bool,
+    },
+    /// Lists entries for dmenu compatible launchers
+    ///
+    /// oxipastectl dmenu | fuzzel -d | oxipastectl dmenu --paste
+    Dmenu {
+        /// Reads the chosen line from stdin and pastes that entry
+        #[arg(long)]
+        paste: bool,
+    },
+    /// Writes the whole history to an archive
+    Export {
+        /// Defaults to stdout
+        file: Option<PathBuf>,
+    },
+    /// Merges an archive into the history
+    Import {
+        file: PathBuf,
+        /// Discards the current history instead of merging
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Prints history changes until interrupted, one per line
+    Watch,
+    /// Shows the state of the daemon, e.g. when copies do not show up
+    Status,
+    /// Stops capturing clipboard changes
+    Pause,
+    /// Continues capturing clipboard changes
+    Resume,
+    /// Appends following copies to a single entry until stopped
+    Collect {
+        /// Stops collecting, the collected entry stays in the history
+        #[arg(long)]
+        stop: bool,
+    },
+    /// Pastes several entries in a row, every paste advances to the next entry
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommand,
+    },
+    /// Named slots kept apart from the history, they are never evicted
+    #[command(alias = "reg")]
+    Register {
+        #[command(subcommand)]
+        command: RegisterCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum RegisterCommand {
+    /// Stores the current clipboard or a history entry
+    Store {
+        name: String,
+        /// Stores this entry instead of the current clipboard
+        id: Option<u32>,
+    },
+    /// Sets a register as the current clipboard
+    Paste { name: String },
+    /// Writes the content of a register to stdout
+    Get { name: String },
+    /// Lists all registers
+    List,
+    /// Removes a register
+    Delete { name: String },
+}
+
+#[derive(Subcommand)]
+enum QueueCommand {
+    /// Appends entries to the queue in the given order
+    Add {
+        #[arg(required = true)]
+        ids: Vec<u32>,
+    },
+    /// Queues the next copies instead, the queue starts after the last one
+    Collect { count: u32 },
+    /// Lists the queued entries in paste order
+    List,
+    /// Removes all entries from the queue
+    Clear,
+}
+
+enum CtlError {
+    Dbus(zbus::Error),
+    Io(io::Error),
+    InvalidSelection(String),
+}
+
+impl From<zbus::Error> for CtlError {
+    fn from(error: zbus::Error) -> Self {
+        Self::Dbus(error)
+    }
+}
+
+impl From<io::Error> for CtlError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl Display for CtlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CtlError::Dbus(zbus::Error::MethodError(name, detail, _)) => {
+                let name = name.as_str();
+                if name == "org.freedesktop.DBus.Error.ServiceUnknown" {
+                    write!(f, "oxipaste_daemon is not running")
+                } else if name == "org.freedesktop.DBus.Error.UnknownMethod" {
+                    write!(
+                        f,
+                        "The running oxipaste_daemon does not support this command, please restart it after updating"
+                    )
+                } else {
+                    write!(
+                        f,
+                        "{}: {}",
+                        name.trim_start_matches(DAEMON_ERROR_PREFIX),
+                        detail.as_deref().unwrap_or("no details")
+                    )
+                }
+            }
+            CtlError::Dbus(error) => write!(f, "{}", error),
+            CtlError::Io(error) => write!(f, "{}", error),
+            CtlError::InvalidSelection(line) if line.is_empty() => write!(f, "No entry selected"),
+            CtlError::InvalidSelection(line) => write!(f, "Invalid selection: {}", line),
+        }
+    }
+}
+
+impl CtlError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CtlError::Dbus(zbus::Error::MethodError(name, _, _)) => match name.as_str() {
+                "org.freedesktop.DBus.Error.ServiceUnknown" => EXIT_DAEMON_UNAVAILABLE,
+                "org.Xetibo.OxiPasteDaemon.Error.InvalidIndex"
+                | "org.Xetibo.OxiPasteDaemon.Error.UnknownRegister" => EXIT_INVALID_ID,
+                _ => EXIT_FAILURE,
+            },
+            // without a session bus the daemon can't be reached either
+            CtlError::Dbus(zbus::Error::Address(_))
+            | CtlError::Dbus(zbus::Error::InputOutput(_)) => EXIT_DAEMON_UNAVAILABLE,
+            _ => EXIT_FAILURE,
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() -> zbus::Result<()> {
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let command = cli.command;
+    if cli.format == Format::Table && command.includes_content() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--content requires --format json",
+            )
+            .exit();
+    }
+    match run(command, cli.format).await {
+        Ok(()) => ExitCode::SUCCESS,
+        // e.g. head or a launcher closing stdin early
+        Err(CtlError::Io(error)) if error.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::from(error.exit_code())
+        }
+    }
+}
+
+impl CliCommand {
+    fn includes_content(&self) -> bool {
+        matches!(
+            self,
+            CliCommand::List { content: true }
+                | CliCommand::Search { content: true, .. }
+                | CliCommand::Get { content: true, .. }
+        )
+    }
+}
+
+async fn run(command: CliCommand, format: Format) -> Result<(), CtlError> {
     let connection = Connection::session().await?;
     let proxy = OxiPasteDbusProxy::new(&connection).await?;
-    proxy.Copy().await?;
+    match command {
+        CliCommand::Copy => proxy.Copy().await?,
+        CliCommand::Add { mime, paste } => {
+            let mut content = Vec::new();
+            io::stdin().lock().read_to_end(&mut content)?;
+            proxy.AddItem(&content, &mime, paste).await?
+        }
+        CliCommand::List { content } => {
+            let entries = proxy.GetEntries().await?;
+            print_entries(&proxy, format, entries, content).await?
+        }
+        CliCommand::Get {
+            id,
+            meta: true,
+            content,
+            ..
+        } => {
+            let entry = proxy.GetEntry(id).await?;
+            print_entries(&proxy, format, vec![entry], content).await?
+        }
+        CliCommand::Get { id, mime, .. } => {
+            let content = match mime {
+                Some(mime) => proxy.GetContentAs(id, &mime).await?,
+                None => proxy.GetContent(id).await?.0,
+            };
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&content)?;
+            stdout.flush()?;
+        }
+        CliCommand::Paste {
+            id,
+            no_trim: false,
+            trim: false,
+            once: false,
+            foreground: false,
+        } => proxy.Paste(id).await?,
+        CliCommand::Paste {
+            id,
+            no_trim,
+            trim,
+            once,
+            foreground,
+        } => {
+            let mut options = HashMap::new();
+            if trim || no_trim {
+                options.insert("trim-newline", Value::from(trim));
+            }
+            options.insert("paste-once", Value::from(once));
+            options.insert("foreground", Value::from(foreground));
+            proxy.PasteWithOptions(id, options).await?
+        }
+        CliCommand::Delete { id } => proxy.DeleteAtIndex(id).await?,
+        CliCommand::Clear { force: true } => proxy.DeleteAllForced().await?,
+        CliCommand::Clear { force: false } => proxy.DeleteAll().await?,
+        CliCommand::Restore => {
+            let restored = proxy.RestorePrevious().await?;
+            if restored > 0 {
+                println!("Restored {} entries", restored);
+            }
+        }
+        CliCommand::Search { query, content } => {
+            let entries = proxy.SearchEntries(&query).await?;
+            print_entries(&proxy, format, entries, content).await?
+        }
+        CliCommand::Pin { id } => proxy.Pin(id).await?,
+        CliCommand::Unpin { id } => proxy.Unpin(id).await?,
+        CliCommand::Burn { id, off } => proxy.SetBurnAfterReading(id, !off).await?,
+        CliCommand::Dmenu { paste: false } => output::print_dmenu(&proxy.GetEntries().await?)?,
+        CliCommand::Dmenu { paste: true } => proxy.Paste(read_selection()?).await?,
+        // the daemon reads and writes archives itself, they may exceed the D-Bus message size
+        CliCommand::Export { file } => match file {
+            Some(file) => {
+                let archive = fs::File::create(&file).map_err(|error| with_path(error, &file))?;
+                proxy.Export(Fd::from(&archive)).await?
+            }
+            None => proxy.Export(Fd::from(&io::stdout())).await?,
+        },
+        CliCommand::Import { file, replace } => {
+            let archive = fs::File::open(&file).map_err(|error| with_path(error, &file))?;
+            let added = if replace {
+                proxy.ImportReplace(Fd::from(&archive)).await?
+            } else {
+                proxy.Import(Fd::from(&archive)).await?
+            };
+            println!("Imported {} entries", added);
+        }
+        CliCommand::Watch => watch(&proxy, format).await?,
+        CliCommand::Status => output::print_status(&proxy.GetDiagnostics().await?, format)?,
+        CliCommand::Pause => proxy.Pause().await?,
+        CliCommand::Resume => proxy.Resume().await?,
+        CliCommand::Collect { stop: false } => proxy.StartCollecting().await?,
+        CliCommand::Collect { stop: true } => proxy.StopCollecting().await?,
+        CliCommand::Queue { command } => match command {
+            QueueCommand::Add { ids } => proxy.QueueAdd(&ids).await?,
+            QueueCommand::Collect { count } => proxy.QueueCollect(count).await?,
+            QueueCommand::List => {
+                let mut entries = Vec::new();
+                for id in proxy.GetQueue().await? {
+                    entries.push(proxy.GetEntry(id).await?);
+                }
+                print_entries(&proxy, format, entries, false).await?
+            }
+            QueueCommand::Clear => proxy.ClearQueue().await?,
+        },
+        CliCommand::Register { command } => match command {
+            RegisterCommand::Store { name, id: Some(id) } => proxy.StoreRegister(&name, id).await?,
+            RegisterCommand::Store { name, id: None } => {
+                proxy.StoreClipboardRegister(&name).await?
+            }
+            RegisterCommand::Paste { name } => proxy.PasteRegister(&name).await?,
+            RegisterCommand::Get { name } => {
+                let (content, _) = proxy.GetRegisterContent(&name).await?;
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(&content)?;
+                stdout.flush()?;
+            }
+            RegisterCommand::List => output::print_registers(&proxy.GetRegisters().await?, format)?,
+            RegisterCommand::Delete { name } => proxy.DeleteRegister(&name).await?,
+        },
+    }
+    Ok(())
+}
+
+fn with_path(error: io::Error, path: &Path) -> io::Error {
+    io::Error::new(error.kind(), format!("{}: {}", path.display(), error))
+}
+
+async fn watch(proxy: &OxiPasteDbusProxy<'_>, format: Format) -> Result<(), CtlError> {
+    let mut added = proxy.receive_item_added().await?;
+    let mut removed = proxy.receive_item_removed().await?;
+    let mut changed = proxy.receive_item_changed().await?;
+    let mut moved = proxy.receive_item_moved().await?;
+    let mut cleared = proxy.receive_history_cleared().await?;
+    let mut paused = proxy.receive_paused_changed().await?;
+    let mut collecting = proxy.receive_collecting_changed().await?;
+    let mut registers = proxy.receive_register_changed().await?;
+    let mut queue = proxy.receive_queue_changed().await?;
+    loop {
+        let event = tokio::select! {
+            Some(signal) = added.next() => {
+                let args = signal.args()?;
+                WatchEvent::Added {
+                    id: args.index,
+                    mime: args.mimetype,
+                    preview: args.preview,
+                }
+            }
+            Some(signal) = removed.next() => {
+                let args = signal.args()?;
+                WatchEvent::Removed {
+                    id: args.index,
+                    mime: args.mimetype,
+                    preview: args.preview,
+                }
+            }
+            Some(signal) = changed.next() => WatchEvent::Changed { id: signal.args()?.index },
+            Some(signal) = moved.next() => {
+                let args = signal.args()?;
+                WatchEvent::Moved {
+                    id: args.index,
+                    to: args.new_position,
+                }
+            }
+            Some(_) = cleared.next() => WatchEvent::Cleared,
+            Some(signal) = paused.next() => {
+                if signal.args()?.paused {
+                    WatchEvent::Paused
+                } else {
+                    WatchEvent::Resumed
+                }
+            }
+            Some(signal) = collecting.next() => WatchEvent::Collecting {
+                active: signal.args()?.collecting,
+            },
+            Some(signal) = registers.next() => WatchEvent::Register {
+                name: signal.args()?.name,
+            },
+            Some(signal) = queue.next() => WatchEvent::Queue {
+                remaining: signal.args()?.remaining,
+            },
+            else => return Ok(()),
+        };
+        output::print_event(&event, format)?;
+    }
+}
+
+/// Parses the id of a line printed by dmenu, the launcher only returns the chosen line.
+fn read_selection() -> Result<u32, CtlError> {
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    let line = line.trim_end_matches(['\n', '\r']);
+    line.split_once('\t')
+        .and_then(|(id, _)| id.parse().ok())
+        .ok_or_else(|| CtlError::InvalidSelection(line.into()))
+}
+
+async fn print_entries(
+    proxy: &OxiPasteDbusProxy<'_>,
+    format: Format,
+    entries: Vec<Entry>,
+    with_content: bool,
+) -> Result<(), CtlError> {
+    match format {
+        Format::Table => output::print_table(&entries)?,
+        Format::Json => {
+            for entry in entries {
+                let content = if with_content {
+                    Some(proxy.GetContent(entry.0).await?.0)
+                } else {
+                    None
+                };
+                output::print_json(&entry, content.as_deref())?;
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,298 @@
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::dbus::{Diagnostics, Entry, RegisterSummary};
+
+const PREVIEW_WIDTH: usize = 80;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned columns for humans
+    Table,
+    /// One JSON object per line, e.g. for jq
+    Json,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum WatchEvent {
+    Added {
+        id: u32,
+        mime: String,
+        preview: String,
+    },
+    Removed {
+        id: u32,
+        mime: String,
+        preview: String,
+    },
+    Changed {
+        id: u32,
+    },
+    Moved {
+        id: u32,
+        to: u32,
+    },
+    Cleared,
+    Paused,
+    Resumed,
+    Collecting {
+        active: bool,
+    },
+    Register {
+        name: String,
+    },
+    Queue {
+        remaining: u32,
+    },
+}
+
+/// Timestamps are null for entries stored before the daemon tracked them.
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    id: u32,
+    mime: &'a str,
+    size: u64,
+    pinned: bool,
+    created: Option<u64>,
+    copied: Option<u64>,
+    preview: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+}
+
+pub fn print_json(entry: &Entry, content: Option<&[u8]>) -> io::Result<()> {
+    let (id, mimetype, size, preview, pinned, created, copied) = entry;
+    let json = JsonEntry {
+        id: *id,
+        mime: mimetype,
+        size: *size,
+        pinned: *pinned,
+        created: (*created != 0).then_some(*created),
+        copied: (*copied != 0).then_some(*copied),
+        preview,
+        content: content.map(|content| STANDARD.encode(content)),
+    };
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &json)?;
+    writeln!(stdout)
+}
+
+pub fn print_table(entries: &[Entry]) -> io::Result<()> {
+    let now = now();
+    let mut stdout = io::stdout().lock();
+    writeln!(
+        stdout,
+        "  ID P MIME                          SIZE COPIED  PREVIEW"
+    )?;
+    for (id, mimetype, size, preview, pinned, _created, copied) in entries {
+        writeln!(
+            stdout,
+            "{:>4} {} {:<24} {:>9} {:>6}  {}",
+            id,
+            if *pinned { "*" } else { " " },
+            mimetype,
+            size,
+            age(now, *copied),
+            one_line_preview(mimetype, preview)
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct JsonRegister<'a> {
+    name: &'a str,
+    mime: &'a str,
+    size: u64,
+    stored: u64,
+    preview: &'a str,
+}
+
+pub fn print_registers(registers: &[RegisterSummary], format: Format) -> io::Result<()> {
+    let now = now();
+    let mut stdout = io::stdout().lock();
+    if format == Format::Table {
+        writeln!(
+            stdout,
+            "NAME     MIME                          SIZE STORED  PREVIEW"
+        )?;
+    }
+    for (name, mimetype, size, preview, stored) in registers {
+        if format == Format::Json {
+            let json = JsonRegister {
+                name,
+                mime: mimetype,
+                size: *size,
+                stored: *stored,
+                preview,
+            };
+            serde_json::to_writer(&mut stdout, &json)?;
+            writeln!(stdout)?;
+            continue;
+        }
+        writeln!(
+            stdout,
+            "{:<8} {:<24} {:>9} {:>6}  {}",
+            name,
+            mimetype,
+            size,
+            age(now, *stored),
+            one_line_preview(mimetype, preview)
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_event(event: &WatchEvent, format: Format) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    if format == Format::Json {
+        serde_json::to_writer(&mut stdout, event)?;
+        return writeln!(stdout);
+    }
+    match event {
+        WatchEvent::Added { id, mime, preview } => {
+            writeln!(
+                stdout,
+                "added {} {} {}",
+                id,
+                mime,
+                one_line_preview(mime, preview)
+            )
+        }
+        WatchEvent::Removed { id, mime, preview } => {
+            writeln!(
+                stdout,
+                "removed {} {} {}",
+                id,
+                mime,
+                one_line_preview(mime, preview)
+            )
+        }
+        WatchEvent::Changed { id } => writeln!(stdout, "changed {}", id),
+        WatchEvent::Moved { id, to } => writeln!(stdout, "moved {} {}", id, to),
+        WatchEvent::Cleared => writeln!(stdout, "cleared"),
+        WatchEvent::Paused => writeln!(stdout, "paused"),
+        WatchEvent::Resumed => writeln!(stdout, "resumed"),
+        WatchEvent::Collecting { active } => writeln!(stdout, "collecting {}", active),
+        WatchEvent::Register { name } => writeln!(stdout, "register {}", name),
+        WatchEvent::Queue { remaining } => writeln!(stdout, "queue {}", remaining),
+    }
+}
+
+pub fn print_status(diagnostics: &Diagnostics, format: Format) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    if format == Format::Json {
+        serde_json::to_writer(&mut stdout, diagnostics)?;
+        return writeln!(stdout);
+    }
+    let now = now();
+    let watcher = if diagnostics.watcher_detail.is_empty() {
+        format!("watcher {}", diagnostics.watcher_state)
+    } else {
+        format!(
+            "watcher {}: {}",
+            diagnostics.watcher_state, diagnostics.watcher_detail
+        )
+    };
+    let last_error = if diagnostics.last_error.is_empty() {
+        "none".into()
+    } else {
+        format!(
+            "{} ({} ago)",
+            diagnostics.last_error,
+            age(now, diagnostics.last_error_time)
+        )
+    };
+    let last_capture = if diagnostics.last_capture == 0 {
+        "never".into()
+    } else {
+        format!("{} ago", age(now, diagnostics.last_capture))
+    };
+    writeln!(stdout, "version       {}", diagnostics.version)?;
+    writeln!(
+        stdout,
+        "backend       {} ({})",
+        diagnostics.backend, watcher
+    )?;
+    writeln!(stdout, "restarts      {}", diagnostics.watcher_restarts)?;
+    writeln!(stdout, "paused        {}", diagnostics.paused)?;
+    writeln!(stdout, "collecting    {}", diagnostics.collecting)?;
+    writeln!(
+        stdout,
+        "entries       {} ({} pinned)",
+        diagnostics.entries, diagnostics.pinned
+    )?;
+    writeln!(stdout, "config        {}", diagnostics.config_path)?;
+    writeln!(
+        stdout,
+        "history file  {} ({})",
+        diagnostics.history_path,
+        human_size(diagnostics.history_file_size)
+    )?;
+    writeln!(
+        stdout,
+        "memory        {}",
+        human_size(diagnostics.memory_usage)
+    )?;
+    writeln!(stdout, "last capture  {}", last_capture)?;
+    writeln!(stdout, "last error    {}", last_error)
+}
+
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1048576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Tabs and newlines are collapsed by the preview, hence the first tab always ends the id.
+pub fn print_dmenu(entries: &[Entry]) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for (id, mimetype, _size, preview, _pinned, _created, _copied) in entries {
+        writeln!(stdout, "{}\t{}", id, one_line_preview(mimetype, preview))?;
+    }
+    Ok(())
+}
+
+fn age(now: u64, timestamp: u64) -> String {
+    if timestamp == 0 {
+        return "-".into();
+    }
+    let seconds = now.saturating_sub(timestamp);
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+fn one_line_preview(mimetype: &str, preview: &str) -> String {
+    if preview.is_empty() {
+        return if mimetype.starts_with("image/") {
+            "[image]".into()
+        } else {
+            "[binary]".into()
+        };
+    }
+    preview
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(PREVIEW_WIDTH)
+        .collect()
+}
@@ -0,0 +1,29 @@
+//! Shared by the daemon and the UI, both include this file as a module.
+
+use std::path::Path;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Calls on_change whenever the config file within dir changes.
+/// The directory is watched, as editors tend to replace files instead of writing them.
+pub fn watch_config(
+    dir: &Path,
+    file_name: &'static str,
+    on_change: impl Fn() + Send + 'static,
+) -> notify::Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if res.is_ok_and(|event| is_config_change(&event, file_name)) {
+            on_change();
+        }
+    })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+fn is_config_change(event: &notify::Event, file_name: &str) -> bool {
+    matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+        && event
+            .paths
+            .iter()
+            .any(|path| path.file_name().is_some_and(|name| name == file_name))
+}
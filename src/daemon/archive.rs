@@ -0,0 +1,235 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::OwnedFd;
+use std::thread;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::config;
+use crate::error::DaemonError;
+use crate::item::Item;
+
+/// Incremented on incompatible changes of the archive format.
+const ARCHIVE_VERSION: u32 = 1;
+
+/// Portable copy of the history, unlike the items file this is versioned and JSON.
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    version: u32,
+    items: Vec<ArchiveItem>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveItem {
+    mime: String,
+    /// base64 encoded
+    content: String,
+    pinned: bool,
+    created: Option<u64>,
+    copied: Option<u64>,
+    /// other mimetypes the content was offered as, missing in older archives
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    variants: Vec<ArchiveVariant>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveVariant {
+    mime: String,
+    /// base64 encoded
+    content: String,
+}
+
+pub fn export(items: &IndexMap<Vec<u8>, Item>) -> Vec<u8> {
+    let archive = Archive {
+        version: ARCHIVE_VERSION,
+        items: items
+            .iter()
+            .map(|(content, item)| ArchiveItem {
+                mime: item.mimetype.clone(),
+                content: STANDARD.encode(content),
+                pinned: item.pinned,
+                created: (item.created != 0).then_some(item.created),
+                copied: (item.copied != 0).then_some(item.copied),
+                variants: item
+                    .variants
+                    .iter()
+                    .map(|(mime, content)| ArchiveVariant {
+                        mime: mime.clone(),
+                        content: STANDARD.encode(content),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+    serde_json::to_vec(&archive).expect("Could not serialize archive.")
+}
+
+/// Either the whole archive is valid or nothing is imported.
+pub fn import(data: &[u8]) -> Result<Vec<(Vec<u8>, Item)>, DaemonError> {
+    let archive: Archive = serde_json::from_slice(data)
+        .map_err(|error| DaemonError::InvalidArgument(format!("Invalid archive: {}", error)))?;
+    if archive.version != ARCHIVE_VERSION {
+        return Err(DaemonError::InvalidArgument(format!(
+            "Unsupported archive version {}, expected {}",
+            archive.version, ARCHIVE_VERSION
+        )));
+    }
+    archive
+        .items
+        .into_iter()
+        .enumerate()
+        .map(|(position, archived)| {
+            let invalid = |reason: &str| {
+                DaemonError::InvalidArgument(format!(
+                    "Invalid archive item {}: {}",
+                    position, reason
+                ))
+            };
+            let content = STANDARD
+                .decode(&archived.content)
+                .map_err(|_| invalid("content is not base64"))?;
+            if content.is_empty() {
+                return Err(invalid("content is empty"));
+            }
+            if archived.mime.is_empty() {
+                return Err(invalid("mimetype is empty"));
+            }
+            // variants end up in the comma separated metadata of the items file
+            let variants = archived
+                .variants
+                .into_iter()
+                .map(|variant| {
+                    if !variant.mime.contains('/') || variant.mime.contains([',', ':', '<']) {
+                        return Err(invalid("variant mimetype is invalid"));
+                    }
+                    let content = STANDARD
+                        .decode(&variant.content)
+                        .map_err(|_| invalid("variant content is not base64"))?;
+                    Ok((variant.mime, content))
+                })
+                .collect::<Result<_, _>>()?;
+            let item = Item {
+                mimetype: archived.mime,
+                pinned: archived.pinned,
+                burn_after_reading: false,
+                created: archived.created.unwrap_or(0),
+                copied: archived.copied.unwrap_or(0),
+                variants,
+            };
+            Ok((content, item))
+        })
+        .collect()
+}
+
+/// Archives easily exceed the size limit of D-Bus messages, hence clients pass a file descriptor.
+/// It might be a pipe, hence it is read on its own thread instead of blocking the interface.
+/// Reading stops at the given limit, the archive is rejected in that case.
+pub async fn read_from(fd: OwnedFd, limit: u64) -> Result<Vec<u8>, DaemonError> {
+    let data = on_thread(move || {
+        let mut data = Vec::new();
+        File::from(fd)
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut data)
+            .map(|_| data)
+    })
+    .await
+    .map_err(|error| DaemonError::InvalidArgument(format!("Could not read archive: {}", error)))?;
+    if data.len() as u64 > limit {
+        return Err(DaemonError::InvalidArgument(format!(
+            "Archive is larger than {} bytes",
+            limit
+        )));
+    }
+    Ok(data)
+}
+
+/// Largest archive a full history could produce, base64 and JSON add about a third.
+pub fn size_limit() -> u64 {
+    let config = config();
+    (config.max_items as u64)
+        .saturating_mul(config.max_item_size as u64)
+        .saturating_mul(2)
+}
+
+pub async fn write_to(fd: OwnedFd, archive: Vec<u8>) -> Result<(), DaemonError> {
+    on_thread(move || File::from(fd).write_all(&archive))
+        .await
+        .map_err(|error| {
+            DaemonError::InvalidArgument(format!("Could not write archive: {}", error))
+        })
+}
+
+/// D-Bus methods don't run within the tokio runtime, spawn_blocking is not available there.
+async fn on_thread<T: Send + 'static>(
+    task: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+    let (sender, receiver) = oneshot::channel();
+    thread::spawn(move || {
+        let _ = sender.send(task());
+    });
+    receiver.await.map_err(io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(archive: &str) -> String {
+        match import(archive.as_bytes()) {
+            Err(DaemonError::InvalidArgument(message)) => message,
+            other => panic!(
+                "Expected invalid archive, got {:?}",
+                other.map(|items| items.len())
+            ),
+        }
+    }
+
+    #[test]
+    fn archive_round_trip() {
+        let mut items = IndexMap::new();
+        let mut text = Item::new("text/plain".into());
+        text.variants = vec![("text/html".into(), b"<b>text</b>".to_vec())];
+        items.insert(b"text".to_vec(), text);
+        let mut image = Item::new("image/png".into());
+        image.pinned = true;
+        items.insert(vec![0x89, b'P', b'N', b'G', 0xff, 0x00], image);
+        items.insert(b"old".to_vec(), Item::from_metadata("text/html".into(), ""));
+
+        let imported = import(&export(&items)).unwrap();
+        assert_eq!(imported.len(), items.len());
+        for ((content, item), (imported_content, imported_item)) in items.iter().zip(&imported) {
+            assert_eq!(content, imported_content);
+            assert_eq!(item.metadata(), imported_item.metadata());
+            assert_eq!(item.mimetype, imported_item.mimetype);
+        }
+    }
+
+    #[test]
+    fn invalid_archives() {
+        assert!(invalid("nope").starts_with("Invalid archive:"));
+        assert!(invalid(r#"{"version":2,"items":[]}"#).starts_with("Unsupported archive version"));
+        let item = |mime: &str, content: &str| {
+            format!(
+                r#"{{"version":1,"items":[{{"mime":"{}","content":"{}","pinned":false}}]}}"#,
+                mime, content
+            )
+        };
+        assert!(invalid(&item("text/plain", "not base64!")).ends_with("content is not base64"));
+        assert!(invalid(&item("text/plain", "")).ends_with("content is empty"));
+        assert!(invalid(&item("", "dGV4dA==")).ends_with("mimetype is empty"));
+        let variant = concat!(
+            r#"{"version":1,"items":[{"mime":"text/plain","content":"dGV4dA==","pinned":false,"#,
+            r#""variants":[{"mime":"text/a,b","content":"dGV4dA=="}]}]}"#
+        );
+        assert!(invalid(variant).ends_with("variant mimetype is invalid"));
+        assert_eq!(
+            import(item("text/plain", "dGV4dA==").as_bytes())
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::os::fd::OwnedFd;
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+use tokio::net::unix::pipe;
+use tokio::sync::mpsc::WeakSender;
+use tokio::task::spawn_blocking;
+use tokio::time::{Instant, timeout};
+use wl_clipboard_rs::paste::{ClipboardType, Error, MimeType, Seat, get_contents, get_mime_types};
+
+use crate::error::DaemonError;
+use crate::item::{Variants, same_mimetype};
+use crate::paste::{self, PasteOptions};
+use crate::{Command, Reply, config};
+
+/// content, mimetype and variants of a clipboard read
+pub type Captured = (Vec<u8>, String, Variants);
+
+/// Upper bound of variants read per copy, sources like browsers offer many image conversions.
+const MAX_VARIANTS: usize = 8;
+
+/// Coalesces bursts of Copy commands into a single clipboard read.
+/// Some apps set the clipboard several times per copy, only the final content is recorded.
+pub struct Capture {
+    sender: WeakSender<Command>,
+    pending: Option<(Reply<()>, Instant)>,
+    /// pastes the daemon serves itself, the clipboard is not captured meanwhile
+    serving: usize,
+}
+
+impl Capture {
+    pub fn new(sender: WeakSender<Command>) -> Self {
+        Self {
+            sender,
+            pending: None,
+            serving: 0,
+        }
+    }
+
+    /// Every request within the debounce window supersedes the previous one and restarts the window.
+    pub fn request(&mut self, reply: Reply<()>) {
+        if let Some((superseded, _)) = self.pending.take() {
+            let _ = superseded.send(Ok(()));
+        }
+        let debounce = Duration::from_millis(config().debounce_ms);
+        self.pending = Some((reply, Instant::now() + debounce));
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|(_, deadline)| *deadline)
+    }
+
+    /// Reads the clipboard in a separate task, the result is sent back as Command::Captured.
+    pub fn start(&mut self) {
+        let Some((reply, _)) = self.pending.take() else {
+            return;
+        };
+        // slow or huge clipboard sources would otherwise block every client
+        let config = config();
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let res = capture_clipboard(
+                config.max_item_size,
+                Duration::from_millis(config.read_timeout_ms),
+            )
+            .await;
+            if let Some(sender) = sender.upgrade() {
+                let _ = sender.send(Command::Captured(res, reply)).await;
+            }
+        });
+    }
+
+    /// Serves a paste with paste-once or foreground.
+    /// With foreground, the reply is sent once serving ended, otherwise as soon as serving started.
+    /// The wl-paste watcher reads every new selection as well, which does not count as a paste.
+    pub fn serve(
+        &mut self,
+        content: Vec<u8>,
+        mimetype: String,
+        options: PasteOptions,
+        watching: bool,
+        burn: bool,
+        reply: Reply<()>,
+    ) {
+        self.serving += 1;
+        let requests = options.paste_once.then_some(1 + watching as usize);
+        let burned = burn.then(|| content.clone());
+        let (started, done) = if options.foreground {
+            (None, Some(reply))
+        } else {
+            (Some(reply), None)
+        };
+        paste::serve(
+            self.sender.clone(),
+            content,
+            mimetype,
+            options.clipboard_options(),
+            requests,
+            started,
+            move |outcome| Command::PasteServed(outcome, done, burned),
+        );
+    }
+
+    pub fn served(&mut self) {
+        self.serving = self.serving.saturating_sub(1);
+    }
+
+    pub fn is_serving(&self) -> bool {
+        self.serving > 0
+    }
+
+    /// Unlike captures, this is neither debounced nor skipped while paused.
+    /// The content is sent back as Command::SetRegister.
+    pub fn read_into_register(&self, name: String, reply: Reply<()>) {
+        let config = config();
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let res = read_clipboard(
+                config.max_item_size,
+                Duration::from_millis(config.read_timeout_ms),
+            )
+            .await;
+            let (content, mimetype) = match res {
+                Ok(Some(captured)) => captured,
+                Ok(None) => {
+                    let error = DaemonError::InvalidArgument("Clipboard is empty".into());
+                    let _ = reply.send(Err(error));
+                    return;
+                }
+                Err(error) => {
+                    let _ = reply.send(Err(error));
+                    return;
+                }
+            };
+            if let Some(sender) = sender.upgrade() {
+                let _ = sender
+                    .send(Command::SetRegister(name, content, mimetype, reply))
+                    .await;
+            }
+        });
+    }
+}
+
+/// Reads the clipboard without blocking the command loop.
+/// Returns None when there is nothing to capture, e.g. an empty clipboard.
+pub async fn read_clipboard(
+    max_size: usize,
+    read_timeout: Duration,
+) -> Result<Option<(Vec<u8>, String)>, DaemonError> {
+    timeout(read_timeout, read(None, max_size))
+        .await
+        .map_err(|_| {
+            DaemonError::backend_unavailable(format!(
+                "Reading the clipboard took longer than {}ms",
+                read_timeout.as_millis()
+            ))
+        })?
+}
+
+/// Reads the clipboard including its variants, as it is recorded in the history.
+pub async fn capture_clipboard(
+    max_size: usize,
+    read_timeout: Duration,
+) -> Result<Option<Captured>, DaemonError> {
+    let Some((contents, mimetype)) = read_clipboard(max_size, read_timeout).await? else {
+        return Ok(None);
+    };
+    let variants = read_variants(&mimetype, max_size, read_timeout).await;
+    Ok(Some((contents, mimetype, variants)))
+}
+
+/// Reads the other mimetypes the clipboard is offered as, e.g. the html of rich text.
+/// Together they may be as large as max_size, failed reads only skip the variant.
+async fn read_variants(mimetype: &str, max_size: usize, read_timeout: Duration) -> Variants {
+    let offered =
+        spawn_blocking(|| get_mime_types(ClipboardType::Regular, Seat::Unspecified)).await;
+    let Ok(Ok(offered)) = offered else {
+        return Vec::new();
+    };
+    let mut offered: Vec<String> = offered
+        .into_iter()
+        .filter(|offered| is_variant(offered, mimetype))
+        .collect();
+    offered.sort();
+    let mut variants = Vec::new();
+    let mut remaining = max_size;
+    for offered in offered.into_iter().take(MAX_VARIANTS) {
+        let res = timeout(read_timeout, read(Some(offered.clone()), remaining)).await;
+        let Ok(Ok(Some((content, _)))) = res else {
+            continue;
+        };
+        if !content.is_empty() {
+            remaining -= content.len();
+            variants.push((offered, content));
+        }
+    }
+    variants
+}
+
+/// Text aliases like UTF8_STRING or a charset parameter don't add anything to the content.
+/// Separators of the items file are left out as well.
+fn is_variant(offered: &str, mimetype: &str) -> bool {
+    offered.contains('/')
+        && !offered.contains([';', ',', ':', '<'])
+        && !same_mimetype(offered, mimetype)
+}
+
+/// Reads the given mimetype, or the best one offered without.
+async fn read(
+    mimetype: Option<String>,
+    max_size: usize,
+) -> Result<Option<(Vec<u8>, String)>, DaemonError> {
+    let res = spawn_blocking(move || {
+        let mimetype = mimetype
+            .as_deref()
+            .map_or(MimeType::Any, MimeType::Specific);
+        get_contents(ClipboardType::Regular, Seat::Unspecified, mimetype)
+    })
+    .await
+    .map_err(DaemonError::backend_unavailable)?;
+    let (pipe, mimetype) = match res {
+        Ok(res) => res,
+        // not an error, just a non pipe state
+        Err(Error::NoSeats) | Err(Error::ClipboardEmpty) | Err(Error::NoMimeType) => {
+            return Ok(None);
+        }
+        Err(error) => return Err(DaemonError::backend_unavailable(error)),
+    };
+    // dropping the async pipe closes it, even if the source never finishes writing
+    let pipe = pipe::Receiver::from_owned_fd(OwnedFd::from(pipe))
+        .map_err(DaemonError::backend_unavailable)?;
+    let mut contents = Vec::new();
+    pipe.take(max_size as u64 + 1)
+        .read_to_end(&mut contents)
+        .await
+        .map_err(DaemonError::backend_unavailable)?;
+    if contents.len() > max_size {
+        return Err(DaemonError::content_too_large(max_size));
+    }
+    Ok(Some((contents, mimetype)))
+}
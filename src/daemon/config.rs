@@ -1,28 +1,74 @@
+use std::fs;
+use std::path::Path;
+
 use serde::{self, Deserialize};
 
+pub const CONFIG_FILE: &str = "config.toml";
+
 pub fn default_config() -> &'static str {
-    r#"max_items=100"#
+    r#"max_items=100
+max_item_size=67108864
+read_timeout_ms=2000
+debounce_ms=100
+collect_separator="\n"
+undo_grace_ms=30000
+trim_newline=true"#
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
     pub max_items: usize,
+    /// in bytes, larger clipboard contents are not captured
+    pub max_item_size: usize,
+    /// reading the clipboard is aborted after this
+    pub read_timeout_ms: u64,
+    /// clipboard changes within this window are captured once, 0 disables it
+    pub debounce_ms: u64,
+    /// inserted between copies that are collected into one entry
+    pub collect_separator: String,
+    /// deletions can be undone by RestorePrevious within this time
+    pub undo_grace_ms: u64,
+    /// removes a trailing newline when pasting, single pastes can override this
+    pub trim_newline: bool,
 }
 
 impl oxilib::Config<ConfigOptional> for Config {
     fn create_from_optional(optional: ConfigOptional) -> Self {
-        let max_items = if let Some(items) = optional.max_items {
-            items
-        } else {
-            100
-        };
-        Self { max_items }
+        let max_items = optional.max_items.unwrap_or(100);
+        let max_item_size = optional.max_item_size.unwrap_or(64 * 1024 * 1024);
+        let read_timeout_ms = optional.read_timeout_ms.unwrap_or(2000);
+        let debounce_ms = optional.debounce_ms.unwrap_or(100);
+        let collect_separator = optional.collect_separator.unwrap_or("\n".into());
+        let undo_grace_ms = optional.undo_grace_ms.unwrap_or(30000);
+        let trim_newline = optional.trim_newline.unwrap_or(true);
+        Self {
+            max_items,
+            max_item_size,
+            read_timeout_ms,
+            debounce_ms,
+            collect_separator,
+            undo_grace_ms,
+            trim_newline,
+        }
     }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ConfigOptional {
     max_items: Option<usize>,
+    max_item_size: Option<usize>,
+    read_timeout_ms: Option<u64>,
+    debounce_ms: Option<u64>,
+    collect_separator: Option<String>,
+    undo_grace_ms: Option<u64>,
+    trim_newline: Option<bool>,
 }
 
 impl oxilib::ConfigOptional for ConfigOptional {}
+
+/// Parses the config file again, errors are returned instead of falling back to defaults.
+pub fn parse_config(path: &Path) -> Result<Config, String> {
+    let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let optional: ConfigOptional = toml::from_str(&contents).map_err(|error| error.to_string())?;
+    Ok(<Config as oxilib::Config<ConfigOptional>>::create_from_optional(optional))
+}
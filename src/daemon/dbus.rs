@@ -1,77 +1,402 @@
-use std::sync::mpsc::{Receiver, Sender};
+use tokio::sync::{
+    mpsc::{Sender, UnboundedReceiver},
+    oneshot,
+};
 
-use crate::{Command, ReverseCommand};
+use crate::{
+    Command, Event, Reply, archive,
+    diagnostics::Diagnostics,
+    error::DaemonError,
+    paste::PasteOptions,
+    registers::RegisterSummary,
+    summary::{Entry, Summary},
+};
 
-use std::{error::Error, future::pending};
-use zbus::{connection, interface};
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+use tokio::time::Instant;
+use zbus::zvariant::{OwnedFd, OwnedValue};
+use zbus::{Connection, connection, interface, object_server::SignalEmitter};
+
+const DBUS_NAME: &str = "org.Xetibo.OxiPasteDaemon";
+const DBUS_PATH: &str = "/org/Xetibo/OxiPasteDaemon";
+/// Time the running daemon gets to write its history and exit when being replaced.
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Incremented on incompatible changes of the interface.
+pub const API_VERSION: u32 = 1;
+/// Optional features clients can check for before using them.
+pub const CAPABILITIES: [&str; 18] = [
+    "summaries",
+    "add-item",
+    "edit",
+    "pins",
+    "reload-config",
+    "pause",
+    "entries",
+    "events",
+    "archive",
+    "diagnostics",
+    "watcher-state",
+    "queue",
+    "collect",
+    "registers",
+    "restore",
+    "paste-options",
+    "burn-after-reading",
+    "mime-variants",
+];
 
 struct OxiPasteDbus {
     sender: Sender<Command>,
-    receiver: Receiver<ReverseCommand>,
 }
 
-unsafe impl Send for OxiPasteDbus {}
-unsafe impl Sync for OxiPasteDbus {}
+impl OxiPasteDbus {
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(Reply<T>) -> Command,
+    ) -> Result<T, DaemonError> {
+        let (reply, response) = oneshot::channel();
+        self.sender
+            .send(command(reply))
+            .await
+            .map_err(|_| DaemonError::backend_unavailable("Could not send command"))?;
+        response
+            .await
+            .map_err(|_| DaemonError::backend_unavailable("Could not receive response"))?
+    }
+}
 
 #[interface(name = "org.Xetibo.OxiPasteDaemon")]
 #[allow(non_snake_case)]
 impl OxiPasteDbus {
-    fn Copy(&mut self) {
-        let _ = self.sender.send(Command::Copy);
+    async fn ApiVersion(&self) -> u32 {
+        API_VERSION
     }
-    fn Paste(&mut self, index: u32) {
-        let _ = self.sender.send(Command::Paste(index as usize));
+    async fn GetCapabilities(&self) -> Vec<String> {
+        CAPABILITIES.into_iter().map(String::from).collect()
     }
-    fn PasteAndDelete(&mut self, index: u32) {
-        let _ = self.sender.send(Command::PasteAndDelete(index as usize));
+    async fn Copy(&self) -> Result<(), DaemonError> {
+        self.request(Command::Copy).await
     }
-    fn GetAll(&mut self) -> Vec<(Vec<u8>, String)> {
-        let mut response = Vec::new();
-        self.sender
-            .send(Command::GetAll)
-            .expect("Could not send command");
-        let res = self.receiver.recv();
-        if let Ok(ReverseCommand::SendAll(items)) = res {
-            response = items;
-        }
-        response
+    async fn AddItem(
+        &self,
+        content: Vec<u8>,
+        mimetype: String,
+        set_as_clipboard: bool,
+    ) -> Result<(), DaemonError> {
+        self.request(|reply| Command::AddItem(content, mimetype, set_as_clipboard, reply))
+            .await
     }
-    fn GetLatest(&mut self) -> (Vec<u8>, String) {
-        let (mut response, mut mimetype) = (Vec::new(), String::from("Empty"));
-        self.sender
-            .send(Command::GetLatest)
-            .expect("Could not send command");
-        let res = self.receiver.recv();
-        if let Ok(ReverseCommand::SendLatest(text)) = res {
-            response = text.0;
-            mimetype = text.1;
-        }
-        (response, mimetype)
+    async fn Paste(&self, index: u32) -> Result<(), DaemonError> {
+        self.request(|reply| Command::Paste(index as usize, reply))
+            .await
+    }
+    /// Options are trim-newline, paste-once and foreground, all booleans.
+    /// With foreground, the call returns once the content was pasted or replaced.
+    async fn PasteWithOptions(
+        &self,
+        index: u32,
+        options: HashMap<String, OwnedValue>,
+    ) -> Result<(), DaemonError> {
+        let options = PasteOptions::parse(options)?;
+        self.request(|reply| Command::PasteWithOptions(index as usize, options, reply))
+            .await
+    }
+    async fn PasteAndDelete(&self, index: u32) -> Result<(), DaemonError> {
+        self.request(|reply| Command::PasteAndDelete(index as usize, reply))
+            .await
+    }
+    async fn GetAll(&self) -> Result<Vec<(Vec<u8>, String)>, DaemonError> {
+        self.request(Command::GetAll).await
+    }
+    async fn GetLatest(&self) -> Result<(Vec<u8>, String), DaemonError> {
+        self.request(Command::GetLatest).await
+    }
+    async fn GetSummaries(&self) -> Result<Vec<Summary>, DaemonError> {
+        self.request(Command::GetSummaries).await
+    }
+    /// Like GetSummaries, but with timestamps and without thumbnails.
+    async fn GetEntries(&self) -> Result<Vec<Entry>, DaemonError> {
+        self.request(|reply| Command::GetEntries(None, reply)).await
+    }
+    async fn SearchEntries(&self, query: String) -> Result<Vec<Entry>, DaemonError> {
+        self.request(|reply| Command::GetEntries(Some(query), reply))
+            .await
+    }
+    async fn GetEntry(&self, index: u32) -> Result<Entry, DaemonError> {
+        self.request(|reply| Command::GetEntry(index as usize, reply))
+            .await
+    }
+    async fn GetContent(&self, index: u32) -> Result<(Vec<u8>, String), DaemonError> {
+        self.request(|reply| Command::GetContent(index as usize, reply))
+            .await
+    }
+    /// The content as one of the mimetypes it was offered as when it was copied.
+    async fn GetContentAs(&self, index: u32, mimetype: String) -> Result<Vec<u8>, DaemonError> {
+        self.request(|reply| Command::GetContentAs(index as usize, mimetype, reply))
+            .await
+    }
+    async fn DeleteAtIndex(&self, index: u32) -> Result<(), DaemonError> {
+        self.request(|reply| Command::DeleteAtIndex(index as usize, reply))
+            .await
+    }
+    /// Removes all items except pinned ones.
+    async fn DeleteAll(&self) -> Result<(), DaemonError> {
+        self.request(|reply| Command::DeleteAll(false, reply)).await
+    }
+    async fn DeleteAllForced(&self) -> Result<(), DaemonError> {
+        self.request(|reply| Command::DeleteAll(true, reply)).await
+    }
+    /// Undoes the last deletion within undo_grace_ms, otherwise the previous entry is pasted.
+    /// Returns the amount of restored entries, 0 when the previous entry was pasted.
+    async fn RestorePrevious(&self) -> Result<u32, DaemonError> {
+        self.request(Command::RestorePrevious).await
+    }
+    async fn Pin(&self, index: u32) -> Result<(), DaemonError> {
+        self.request(|reply| Command::SetPinned(index as usize, true, reply))
+            .await
+    }
+    async fn Unpin(&self, index: u32) -> Result<(), DaemonError> {
+        self.request(|reply| Command::SetPinned(index as usize, false, reply))
+            .await
+    }
+    /// The entry is removed on its next paste, the clipboard is cleared after that paste.
+    async fn SetBurnAfterReading(&self, index: u32, burn: bool) -> Result<(), DaemonError> {
+        self.request(|reply| Command::SetBurnAfterReading(index as usize, burn, reply))
+            .await
+    }
+    async fn ReplaceContent(
+        &self,
+        index: u32,
+        content: Vec<u8>,
+        mimetype: String,
+    ) -> Result<(), DaemonError> {
+        self.request(|reply| Command::ReplaceContent(index as usize, content, mimetype, reply))
+            .await
+    }
+    async fn MoveToTop(&self, index: u32) -> Result<(), DaemonError> {
+        self.request(|reply| Command::Move(index as usize, 0, reply))
+            .await
+    }
+    async fn Move(&self, index: u32, new_position: u32) -> Result<(), DaemonError> {
+        self.request(|reply| Command::Move(index as usize, new_position as usize, reply))
+            .await
+    }
+    /// Stops capturing clipboard changes until resumed.
+    async fn Pause(&self) -> Result<(), DaemonError> {
+        self.request(|reply| Command::SetPaused(true, reply)).await
+    }
+    async fn Resume(&self) -> Result<(), DaemonError> {
+        self.request(|reply| Command::SetPaused(false, reply)).await
+    }
+    /// Appends following copies to a single entry, separated by collect_separator.
+    async fn StartCollecting(&self) -> Result<(), DaemonError> {
+        self.request(|reply| Command::SetCollecting(true, reply))
+            .await
+    }
+    async fn StopCollecting(&self) -> Result<(), DaemonError> {
+        self.request(|reply| Command::SetCollecting(false, reply))
+            .await
     }
-    fn DeleteAtIndex(&mut self, index: u32) {
-        let _ = self.sender.send(Command::DeleteAtIndex(index as usize));
+    /// Writes the whole history including binary items as a JSON archive to the file descriptor.
+    async fn Export(&self, fd: OwnedFd) -> Result<(), DaemonError> {
+        let data = self.request(Command::Export).await?;
+        archive::write_to(fd.into(), data).await
     }
-    fn DeleteAll(&mut self) {
-        let _ = self.sender.send(Command::DeleteAll);
+    /// Merges the archive read from the file descriptor into the history,
+    /// returns the amount of added items that were not evicted right away.
+    async fn Import(&self, fd: OwnedFd) -> Result<u32, DaemonError> {
+        let data = archive::read_from(fd.into(), archive::size_limit()).await?;
+        self.request(|reply| Command::Import(data, false, reply))
+            .await
     }
-    fn ShutDown(&mut self) {
-        let _ = self.sender.send(Command::ShutDown);
+    async fn ImportReplace(&self, fd: OwnedFd) -> Result<u32, DaemonError> {
+        let data = archive::read_from(fd.into(), archive::size_limit()).await?;
+        self.request(|reply| Command::Import(data, true, reply))
+            .await
     }
+    async fn GetDiagnostics(&self) -> Result<Diagnostics, DaemonError> {
+        self.request(Command::GetDiagnostics).await
+    }
+    /// State name, details and the amount of restarts of the clipboard watcher.
+    async fn GetWatcherState(&self) -> Result<(String, String, u32), DaemonError> {
+        self.request(Command::GetWatcherState).await
+    }
+    /// Queued items are put into the clipboard one after another, each paste advances the queue.
+    async fn QueueAdd(&self, indexes: Vec<u32>) -> Result<(), DaemonError> {
+        let indexes = indexes.into_iter().map(|index| index as usize).collect();
+        self.request(|reply| Command::QueueAdd(indexes, reply))
+            .await
+    }
+    /// Queues the next count copies instead of existing items.
+    async fn QueueCollect(&self, count: u32) -> Result<(), DaemonError> {
+        self.request(|reply| Command::QueueCollect(count, reply))
+            .await
+    }
+    /// Indexes of the queued items in paste order.
+    async fn GetQueue(&self) -> Result<Vec<u32>, DaemonError> {
+        self.request(Command::GetQueue).await
+    }
+    async fn ClearQueue(&self) -> Result<(), DaemonError> {
+        self.request(Command::ClearQueue).await
+    }
+    /// Named slots that are kept until deleted, storing overwrites the previous content.
+    async fn StoreRegister(&self, name: String, index: u32) -> Result<(), DaemonError> {
+        self.request(|reply| Command::StoreRegister(name, index as usize, reply))
+            .await
+    }
+    /// The clipboard is read directly, this works while paused as well.
+    async fn StoreClipboardRegister(&self, name: String) -> Result<(), DaemonError> {
+        self.request(|reply| Command::StoreClipboardRegister(name, reply))
+            .await
+    }
+    async fn PasteRegister(&self, name: String) -> Result<(), DaemonError> {
+        self.request(|reply| Command::PasteRegister(name, reply))
+            .await
+    }
+    /// Sorted by name.
+    async fn GetRegisters(&self) -> Result<Vec<RegisterSummary>, DaemonError> {
+        self.request(Command::GetRegisters).await
+    }
+    async fn GetRegisterContent(&self, name: String) -> Result<(Vec<u8>, String), DaemonError> {
+        self.request(|reply| Command::GetRegisterContent(name, reply))
+            .await
+    }
+    async fn DeleteRegister(&self, name: String) -> Result<(), DaemonError> {
+        self.request(|reply| Command::DeleteRegister(name, reply))
+            .await
+    }
+    async fn ReloadConfig(&self) -> Result<(), DaemonError> {
+        self.request(Command::ReloadConfig).await
+    }
+    async fn ShutDown(&self) -> Result<(), DaemonError> {
+        self.request(Command::ShutDown).await
+    }
+
+    #[zbus(signal)]
+    async fn item_added(
+        emitter: &SignalEmitter<'_>,
+        index: u32,
+        mimetype: &str,
+        preview: &str,
+    ) -> zbus::Result<()>;
+    /// Also emitted for evicted items.
+    #[zbus(signal)]
+    async fn item_removed(
+        emitter: &SignalEmitter<'_>,
+        index: u32,
+        mimetype: &str,
+        preview: &str,
+    ) -> zbus::Result<()>;
+    #[zbus(signal)]
+    async fn item_changed(emitter: &SignalEmitter<'_>, index: u32) -> zbus::Result<()>;
+    #[zbus(signal)]
+    async fn item_moved(
+        emitter: &SignalEmitter<'_>,
+        index: u32,
+        new_position: u32,
+    ) -> zbus::Result<()>;
+    /// Pinned items remain unless the history was cleared forcefully.
+    #[zbus(signal)]
+    async fn history_cleared(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+    #[zbus(signal)]
+    async fn paused_changed(emitter: &SignalEmitter<'_>, paused: bool) -> zbus::Result<()>;
+    #[zbus(signal)]
+    async fn collecting_changed(emitter: &SignalEmitter<'_>, collecting: bool) -> zbus::Result<()>;
+    #[zbus(signal)]
+    async fn watcher_state_changed(
+        emitter: &SignalEmitter<'_>,
+        state: &str,
+        detail: &str,
+    ) -> zbus::Result<()>;
+    /// Emitted when a register is stored or deleted.
+    #[zbus(signal)]
+    async fn register_changed(emitter: &SignalEmitter<'_>, name: &str) -> zbus::Result<()>;
+    #[zbus(signal)]
+    async fn queue_changed(emitter: &SignalEmitter<'_>, remaining: u32) -> zbus::Result<()>;
 }
 
-pub async fn run(
+async fn emit_event(emitter: &SignalEmitter<'_>, event: Event) -> zbus::Result<()> {
+    match event {
+        Event::ItemAdded(index, mimetype, preview) => {
+            OxiPasteDbus::item_added(emitter, index as u32, &mimetype, &preview).await
+        }
+        Event::ItemRemoved(index, mimetype, preview) => {
+            OxiPasteDbus::item_removed(emitter, index as u32, &mimetype, &preview).await
+        }
+        Event::ItemChanged(index) => OxiPasteDbus::item_changed(emitter, index as u32).await,
+        Event::ItemMoved(index, new_position) => {
+            OxiPasteDbus::item_moved(emitter, index as u32, new_position as u32).await
+        }
+        Event::HistoryCleared => OxiPasteDbus::history_cleared(emitter).await,
+        Event::PausedChanged(paused) => OxiPasteDbus::paused_changed(emitter, paused).await,
+        Event::CollectingChanged(collecting) => {
+            OxiPasteDbus::collecting_changed(emitter, collecting).await
+        }
+        Event::WatcherStateChanged(state, detail) => {
+            OxiPasteDbus::watcher_state_changed(emitter, state, &detail).await
+        }
+        Event::RegisterChanged(name) => OxiPasteDbus::register_changed(emitter, &name).await,
+        Event::QueueChanged(remaining) => {
+            OxiPasteDbus::queue_changed(emitter, remaining as u32).await
+        }
+    }
+}
+
+/// Serves the interface and acquires the bus name, only one daemon may own it at a time.
+/// With replace, the running daemon is asked to shut down and the name is taken over.
+pub async fn connect(
     sender: Sender<Command>,
-    receiver: Receiver<ReverseCommand>,
-) -> Result<(), Box<dyn Error>> {
-    let oxipaste_dbus = OxiPasteDbus { sender, receiver };
-    let _conn = connection::Builder::session()?
-        .name("org.Xetibo.OxiPasteDaemon")?
-        .serve_at("/org/Xetibo/OxiPasteDaemon", oxipaste_dbus)?
+    replace: bool,
+) -> Result<Connection, Box<dyn Error + Send + Sync>> {
+    let oxipaste_dbus = OxiPasteDbus { sender };
+    let conn = connection::Builder::session()?
+        .serve_at(DBUS_PATH, oxipaste_dbus)?
         .build()
         .await?;
+    match conn.request_name(DBUS_NAME).await {
+        Ok(()) => return Ok(conn),
+        Err(zbus::Error::NameTaken) if replace => (),
+        Err(zbus::Error::NameTaken) => {
+            return Err("oxipaste_daemon is already running, use --replace to replace it".into());
+        }
+        Err(error) => return Err(error.into()),
+    }
 
-    // Do other things or go to wait forever
-    pending::<()>().await;
+    // the running daemon writes its history before exiting
+    conn.call_method(Some(DBUS_NAME), DBUS_PATH, Some(DBUS_NAME), "ShutDown", &())
+        .await?;
+    let start = Instant::now();
+    loop {
+        match conn.request_name(DBUS_NAME).await {
+            Ok(()) => return Ok(conn),
+            Err(zbus::Error::NameTaken) if start.elapsed() < REPLACE_TIMEOUT => {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Err(zbus::Error::NameTaken) => {
+                return Err("The running oxipaste_daemon did not exit in time".into());
+            }
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+pub async fn forward_events(
+    conn: Connection,
+    mut events: UnboundedReceiver<Event>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let interface = conn
+        .object_server()
+        .interface::<_, OxiPasteDbus>(DBUS_PATH)
+        .await?;
+
+    // forward history changes until the daemon shuts down
+    while let Some(event) = events.recv().await {
+        if let Err(error) = emit_event(interface.signal_emitter(), event).await {
+            eprintln!("Could not emit signal: {}", error);
+        }
+    }
 
     Ok(())
 }
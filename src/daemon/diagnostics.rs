@@ -0,0 +1,51 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Type;
+
+/// Only the wl-paste watcher exists for now, see watcher.rs.
+pub const BACKEND: &str = "wl-paste";
+
+/// Everything needed to find out why a copy did not show up.
+/// Timestamps are unix seconds, 0 if it never happened.
+#[derive(Debug, Serialize, Deserialize, Type)]
+pub struct Diagnostics {
+    pub version: String,
+    pub backend: String,
+    pub watcher_running: bool,
+    pub watcher_state: String,
+    pub watcher_detail: String,
+    pub watcher_restarts: u32,
+    pub paused: bool,
+    pub collecting: bool,
+    pub config_path: String,
+    pub history_path: String,
+    pub history_file_size: u64,
+    pub entries: u32,
+    pub pinned: u32,
+    /// resident memory of the daemon in bytes
+    pub memory_usage: u64,
+    pub last_capture: u64,
+    pub last_error: String,
+    pub last_error_time: u64,
+}
+
+/// Resident set size, 0 where /proc is not available.
+pub fn memory_usage() -> u64 {
+    let Ok(status) = fs::read_to_string("/proc/self/status") else {
+        return 0;
+    };
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| {
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .map(|kilobytes| kilobytes * 1024)
+        .unwrap_or(0)
+}
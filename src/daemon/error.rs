@@ -0,0 +1,36 @@
+use zbus::DBusError;
+
+/// Errors sent to D-Bus clients as `org.Xetibo.OxiPasteDaemon.Error.*`.
+#[derive(Debug, DBusError)]
+#[zbus(prefix = "org.Xetibo.OxiPasteDaemon.Error")]
+pub enum DaemonError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    InvalidIndex(String),
+    InvalidArgument(String),
+    InvalidConfig(String),
+    BackendUnavailable(String),
+    ContentTooLarge(String),
+    UnknownRegister(String),
+    UnavailableMimetype(String),
+}
+
+impl DaemonError {
+    pub fn invalid_index(index: usize, len: usize) -> Self {
+        Self::InvalidIndex(format!(
+            "Index {} is out of range, history contains {} items",
+            index, len
+        ))
+    }
+
+    pub fn content_too_large(max_size: usize) -> Self {
+        Self::ContentTooLarge(format!(
+            "Content is larger than max_item_size ({} bytes)",
+            max_size
+        ))
+    }
+
+    pub fn backend_unavailable(reason: impl std::fmt::Display) -> Self {
+        Self::BackendUnavailable(reason.to_string())
+    }
+}
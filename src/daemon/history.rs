@@ -0,0 +1,986 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use indexmap::IndexMap;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+use wl_clipboard_rs::copy::{Options, Source};
+
+use crate::archive;
+use crate::config::CONFIG_FILE;
+use crate::diagnostics::{self, BACKEND, Diagnostics};
+use crate::error::DaemonError;
+use crate::item::{Item, Variants, now, same_mimetype};
+use crate::paste::ServeOutcome;
+use crate::queue::PasteQueue;
+use crate::registers::{Register, RegisterSummary, Registers};
+use crate::summary::{Entry, Summary, SummaryCache, create_entry, entry_preview, hash_content};
+use crate::watcher::{WatcherState, WatcherStatus};
+use crate::{CONFIG_DIR, Event, Reply, config};
+
+/// Marks base64 encoded content in the items file.
+const BASE64_FLAG: &str = "base64";
+
+/// Deleted items with their original indexes.
+type Deleted = Vec<(usize, Vec<u8>, Item)>;
+
+/// Clipboard history, the newest item is at index 0.
+pub struct History {
+    items: IndexMap<Vec<u8>, Item>,
+    summary_cache: SummaryCache,
+    events: UnboundedSender<Event>,
+    paused: bool,
+    collecting: bool,
+    /// the growing entry and whether collecting created it, existing entries are left as they are
+    collected: Option<(Vec<u8>, bool)>,
+    /// content the daemon copied itself while collecting, it is not joined when captured again
+    written: Option<Vec<u8>>,
+    queue: PasteQueue,
+    registers: Registers,
+    /// the last deletion with the original indexes, it can be undone until the grace period ends
+    deleted: Option<(Instant, Deleted)>,
+    last_capture: u64,
+    last_error: String,
+    last_error_time: u64,
+}
+
+impl History {
+    pub fn load(events: UnboundedSender<Event>) -> Self {
+        let history = Self {
+            items: get_items_from_file(),
+            summary_cache: SummaryCache::default(),
+            events,
+            paused: false,
+            collecting: false,
+            collected: None,
+            written: None,
+            queue: PasteQueue::default(),
+            registers: Registers::load(),
+            deleted: None,
+            last_capture: 0,
+            last_error: String::new(),
+            last_error_time: 0,
+        };
+        history.summary_cache.prepare(&history.items);
+        history
+    }
+
+    fn emit(&self, event: Event) {
+        // the receiver is only gone when the daemon shuts down
+        let _ = self.events.send(event);
+    }
+
+    pub fn write_to_file(&self) {
+        write_items_to_file(&self.items);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Adds content read by a capture task, content read while pausing is dropped.
+    pub fn capture(&mut self, contents: Vec<u8>, mimetype: String, variants: Variants) {
+        if self.paused {
+            return;
+        }
+        self.last_capture = now();
+        // images can't be joined, they are captured as usual
+        if self.collecting && !mimetype.starts_with("image/") && !self.was_written(&contents) {
+            self.collect(contents, mimetype);
+            return;
+        }
+        if self.queue.collecting > 0 {
+            self.queue.collecting -= 1;
+            self.queue.entries.push_back(contents.clone());
+            self.emit_queue_changed();
+        }
+        self.insert(contents, mimetype, variants);
+    }
+
+    /// While collecting, copies are appended to a single entry instead of creating new ones.
+    /// Every start begins a new entry.
+    pub fn set_collecting(&mut self, collecting: bool) {
+        self.collected = None;
+        self.written = None;
+        if self.collecting != collecting {
+            self.collecting = collecting;
+            self.emit(Event::CollectingChanged(collecting));
+        }
+    }
+
+    /// Whether the content was copied by the daemon itself, e.g. by pasting an entry.
+    /// The trailing newline might have been trimmed while copying.
+    fn was_written(&mut self, contents: &[u8]) -> bool {
+        self.written.take().is_some_and(|written| {
+            written == contents || written.strip_suffix(b"\n") == Some(contents)
+        })
+    }
+
+    /// The collected entry is moved to the top with every copy.
+    fn collect(&mut self, contents: Vec<u8>, mimetype: String) {
+        let Some((previous, created)) = self.collected.take() else {
+            let created = !self.items.contains_key(&contents);
+            self.insert(contents.clone(), mimetype, Vec::new());
+            self.collected = Some((contents, created));
+            return;
+        };
+        // copying the collected entry itself would double it
+        if contents == previous {
+            self.collected = Some((previous, created));
+            return;
+        }
+        let mut merged = previous.clone();
+        merged.extend_from_slice(config().collect_separator.as_bytes());
+        merged.extend_from_slice(&contents);
+        // a collected entry can't grow beyond the limit either, the copy starts a new one
+        if check_size(&merged).is_err() {
+            self.collect(contents, mimetype);
+            return;
+        }
+        let mut item = match self.items.get_full(&previous) {
+            Some((index, _, _)) if created => {
+                let (_, item) = self.items.shift_remove_index(index).unwrap();
+                self.emit(removed_event(index, &previous, &item));
+                item
+            }
+            Some((_, _, existing)) => Item::new(existing.mimetype.clone()),
+            // deleted in the meantime, the collected content is kept anyway
+            None => Item::new(mimetype),
+        };
+        item.touch();
+        // the variants only belonged to the first copy
+        item.variants.clear();
+        if let Some((index, content, existing)) = self.items.shift_remove_full(&merged) {
+            self.emit(removed_event(index, &content, &existing));
+        }
+        let preview = entry_preview(&merged, &item);
+        self.emit(Event::ItemAdded(0, item.mimetype.clone(), preview));
+        self.items.shift_insert(0, merged.clone(), item);
+        self.collected = Some((merged, true));
+        self.evict();
+    }
+
+    /// Backend and config errors are kept for diagnostics, errors of clients like invalid indexes are not.
+    pub fn track<T>(&mut self, res: Result<T, DaemonError>) -> Result<T, DaemonError> {
+        if let Err(
+            error @ (DaemonError::BackendUnavailable(_)
+            | DaemonError::InvalidConfig(_)
+            | DaemonError::ContentTooLarge(_)),
+        ) = &res
+        {
+            self.last_error = error
+                .to_string()
+                .trim_start_matches("org.Xetibo.OxiPasteDaemon.Error.")
+                .into();
+            eprintln!("{}", self.last_error);
+            self.last_error_time = now();
+        }
+        res
+    }
+
+    pub fn diagnostics(&self, watcher: &WatcherStatus) -> Diagnostics {
+        let history_path = items_file_path();
+        Diagnostics {
+            version: env!("CARGO_PKG_VERSION").into(),
+            backend: BACKEND.into(),
+            watcher_running: matches!(watcher.state, WatcherState::Running),
+            watcher_state: watcher.state.name().into(),
+            watcher_detail: watcher.state.detail(),
+            watcher_restarts: watcher.restarts,
+            paused: self.paused,
+            collecting: self.collecting,
+            config_path: CONFIG_DIR.join(CONFIG_FILE).display().to_string(),
+            history_file_size: fs::metadata(&history_path).map_or(0, |metadata| metadata.len()),
+            history_path: history_path.display().to_string(),
+            entries: self.items.len() as u32,
+            pinned: self.items.values().filter(|item| item.pinned).count() as u32,
+            memory_usage: diagnostics::memory_usage(),
+            last_capture: self.last_capture,
+            last_error: self.last_error.clone(),
+            last_error_time: self.last_error_time,
+        }
+    }
+
+    /// While paused, clipboard changes are not captured.
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused != paused {
+            self.paused = paused;
+            self.emit(Event::PausedChanged(paused));
+        }
+    }
+
+    /// Adds an item that was not captured from the clipboard, e.g. from scripts.
+    pub fn add(
+        &mut self,
+        contents: Vec<u8>,
+        mimetype: String,
+        set_as_clipboard: bool,
+    ) -> Result<(), DaemonError> {
+        if contents.is_empty() {
+            return Err(DaemonError::InvalidArgument("Content is empty".into()));
+        }
+        if mimetype.is_empty() {
+            return Err(DaemonError::InvalidArgument("Mimetype is empty".into()));
+        }
+        check_size(&contents)?;
+        if set_as_clipboard {
+            self.copy_to_clipboard(contents.clone(), mimetype.clone())?;
+        }
+        self.insert(contents, mimetype, Vec::new());
+        Ok(())
+    }
+
+    /// Copying existing content again keeps its variants, unless new ones were offered.
+    fn insert(&mut self, contents: Vec<u8>, mimetype: String, variants: Variants) {
+        if let Some((index, _, item)) = self.items.get_full_mut(&contents) {
+            item.touch();
+            if !variants.is_empty() {
+                item.variants = variants;
+            }
+            self.emit(Event::ItemChanged(index));
+            return;
+        }
+        let item = Item {
+            variants,
+            ..Item::new(mimetype)
+        };
+        let preview = entry_preview(&contents, &item);
+        self.emit(Event::ItemAdded(0, item.mimetype.clone(), preview));
+        self.summary_cache.prepare([(&contents, &item)]);
+        self.items.shift_insert(0, contents, item);
+        self.evict();
+    }
+
+    /// Removes the oldest items above max_items, pinned items are never evicted.
+    pub fn evict(&mut self) {
+        // clean memory in order to not leak
+        for (index, content, item) in evict_items(&mut self.items, config().max_items) {
+            self.emit(removed_event(index, &content, &item));
+        }
+    }
+
+    pub fn delete(&mut self, index: usize) -> Result<(), DaemonError> {
+        let (content, item) = self.remove(index)?;
+        self.deleted = Some((Instant::now(), vec![(index, content, item)]));
+        Ok(())
+    }
+
+    /// Unlike delete, this can't be undone.
+    fn remove(&mut self, index: usize) -> Result<(Vec<u8>, Item), DaemonError> {
+        let (content, item) = self
+            .items
+            .shift_remove_index(index)
+            .ok_or_else(|| DaemonError::invalid_index(index, self.items.len()))?;
+        self.emit(removed_event(index, &content, &item));
+        Ok((content, item))
+    }
+
+    /// Pinned items are only removed when forced.
+    pub fn clear(&mut self, force: bool) {
+        let deleted = clear_items(&mut self.items, force);
+        // clearing nothing keeps an earlier deletion restorable
+        if !deleted.is_empty() {
+            self.deleted = Some((Instant::now(), deleted));
+        }
+        if force {
+            clear_items_file();
+        } else {
+            self.write_to_file();
+        }
+        self.emit(Event::HistoryCleared);
+    }
+
+    pub fn set_pinned(&mut self, index: usize, pinned: bool) -> Result<(), DaemonError> {
+        let len = self.items.len();
+        let (_, item) = self
+            .items
+            .get_index_mut(index)
+            .ok_or_else(|| DaemonError::invalid_index(index, len))?;
+        item.pinned = pinned;
+        self.write_to_file();
+        self.emit(Event::ItemChanged(index));
+        Ok(())
+    }
+
+    /// Undoes the last deletion within the grace period, otherwise the previous entry is pasted.
+    /// Pasting moves the entry to the top, restoring twice returns to the original clipboard.
+    /// Returns the amount of restored entries, 0 when the previous entry was pasted.
+    pub fn restore_previous(&mut self) -> Result<u32, DaemonError> {
+        let undo = self.undo_pending();
+        match self.deleted.take() {
+            Some((_, deleted)) if undo => Ok(self.restore_deleted(deleted)),
+            _ => {
+                if self.items.len() < 2 {
+                    return Err(DaemonError::InvalidArgument(
+                        "There is no previous entry to restore".into(),
+                    ));
+                }
+                self.paste(1)?;
+                self.move_item(1, 0)?;
+                Ok(0)
+            }
+        }
+    }
+
+    fn undo_pending(&self) -> bool {
+        let grace = Duration::from_millis(config().undo_grace_ms);
+        matches!(&self.deleted, Some((time, _)) if time.elapsed() <= grace)
+    }
+
+    /// Whether restore_previous would paste the previous entry, and that entry burns after reading.
+    pub fn restores_burning_entry(&self) -> bool {
+        !self.undo_pending() && self.burns_after_reading(1)
+    }
+
+    /// Entries are put back at their original indexes, content copied again meanwhile is skipped.
+    fn restore_deleted(&mut self, deleted: Deleted) -> u32 {
+        let mut restored = 0;
+        for (index, content, item) in deleted {
+            if self.items.contains_key(&content) {
+                continue;
+            }
+            let index = index.min(self.items.len());
+            let preview = entry_preview(&content, &item);
+            self.emit(Event::ItemAdded(index, item.mimetype.clone(), preview));
+            self.summary_cache.prepare([(&content, &item)]);
+            self.items.shift_insert(index, content, item);
+            restored += 1;
+        }
+        self.evict();
+        self.write_to_file();
+        restored
+    }
+
+    pub fn set_burn_after_reading(&mut self, index: usize, burn: bool) -> Result<(), DaemonError> {
+        let len = self.items.len();
+        let (_, item) = self
+            .items
+            .get_index_mut(index)
+            .ok_or_else(|| DaemonError::invalid_index(index, len))?;
+        item.burn_after_reading = burn;
+        self.write_to_file();
+        self.emit(Event::ItemChanged(index));
+        Ok(())
+    }
+
+    pub fn burns_after_reading(&self, index: usize) -> bool {
+        self.items
+            .get_index(index)
+            .is_some_and(|(_, item)| item.burn_after_reading)
+    }
+
+    pub fn latest(&self) -> (Vec<u8>, String) {
+        match self.items.first() {
+            Some((content, item)) => (content.clone(), item.mimetype.clone()),
+            None => (Vec::new(), String::from("Empty")),
+        }
+    }
+
+    pub fn all(&self) -> Vec<(Vec<u8>, String)> {
+        self.items
+            .iter()
+            .map(|(content, item)| (content.clone(), item.mimetype.clone()))
+            .collect()
+    }
+
+    pub fn summaries(&self, reply: Reply<Vec<Summary>>) {
+        self.summary_cache.summaries(&self.items, reply);
+    }
+
+    /// Entries of all items, or only the ones matching the query.
+    /// The search is case insensitive and covers mimetypes and the full text of items.
+    pub fn entries(&self, query: Option<&str>) -> Vec<Entry> {
+        let query = query.map(str::to_lowercase);
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, (content, item))| {
+                query
+                    .as_ref()
+                    .is_none_or(|query| matches_query(content, item, query))
+            })
+            .map(|(index, (content, item))| create_entry(index, content, item))
+            .collect()
+    }
+
+    pub fn entry(&self, index: usize) -> Result<Entry, DaemonError> {
+        self.items
+            .get_index(index)
+            .map(|(content, item)| create_entry(index, content, item))
+            .ok_or_else(|| DaemonError::invalid_index(index, self.items.len()))
+    }
+
+    pub fn content(&self, index: usize) -> Result<(Vec<u8>, String), DaemonError> {
+        self.items
+            .get_index(index)
+            .map(|(content, item)| (content.clone(), item.mimetype.clone()))
+            .ok_or_else(|| DaemonError::invalid_index(index, self.items.len()))
+    }
+
+    /// Either the content itself or one of its variants, parameters like charset are ignored.
+    pub fn content_as(&self, index: usize, mimetype: &str) -> Result<Vec<u8>, DaemonError> {
+        let (content, item) = self
+            .items
+            .get_index(index)
+            .ok_or_else(|| DaemonError::invalid_index(index, self.items.len()))?;
+        if same_mimetype(&item.mimetype, mimetype) {
+            return Ok(content.clone());
+        }
+        if let Some((_, variant)) = item
+            .variants
+            .iter()
+            .find(|(variant, _)| same_mimetype(variant, mimetype))
+        {
+            return Ok(variant.clone());
+        }
+        let available: Vec<&str> = std::iter::once(&item.mimetype)
+            .chain(item.variants.iter().map(|(variant, _)| variant))
+            .map(String::as_str)
+            .collect();
+        Err(DaemonError::UnavailableMimetype(format!(
+            "Entry {} is only available as {}",
+            index,
+            available.join(", ")
+        )))
+    }
+
+    pub fn paste(&mut self, index: usize) -> Result<(), DaemonError> {
+        let (content, mimetype) = self.content(index)?;
+        self.copy_to_clipboard(content, mimetype)
+    }
+
+    fn copy_to_clipboard(&mut self, content: Vec<u8>, mimetype: String) -> Result<(), DaemonError> {
+        self.write_clipboard(clipboard_options(), content, mimetype)
+    }
+
+    /// Copies of the daemon itself are remembered while collecting, see was_written.
+    pub fn write_clipboard(
+        &mut self,
+        opts: Options,
+        content: Vec<u8>,
+        mimetype: String,
+    ) -> Result<(), DaemonError> {
+        let written = self.collecting.then(|| content.clone());
+        write_clipboard(opts, content, mimetype)?;
+        self.written = written;
+        Ok(())
+    }
+
+    pub fn paste_and_delete(&mut self, index: usize) -> Result<(), DaemonError> {
+        self.paste(index)?;
+        self.delete(index)
+    }
+
+    pub fn replace(
+        &mut self,
+        index: usize,
+        contents: Vec<u8>,
+        mimetype: String,
+    ) -> Result<(), DaemonError> {
+        self.ensure_index(index)?;
+        if mimetype.is_empty() {
+            return Err(DaemonError::InvalidArgument("Mimetype is empty".into()));
+        }
+        check_size(&contents)?;
+        match self.items.get_index_of(&contents) {
+            Some(existing) if existing != index => {
+                return Err(DaemonError::InvalidArgument(format!(
+                    "Content already exists at index {}",
+                    existing
+                )));
+            }
+            Some(_) => {
+                self.items[index].mimetype = mimetype;
+            }
+            None => {
+                let (_, mut item) = self.items.shift_remove_index(index).unwrap();
+                item.mimetype = mimetype;
+                item.variants.clear();
+                self.items.shift_insert(index, contents, item);
+            }
+        }
+        self.summary_cache.prepare(self.items.get_index(index));
+        self.write_to_file();
+        self.emit(Event::ItemChanged(index));
+        Ok(())
+    }
+
+    pub fn move_item(&mut self, index: usize, new_position: usize) -> Result<(), DaemonError> {
+        self.ensure_index(index)?;
+        self.ensure_index(new_position)?;
+        self.items.move_index(index, new_position);
+        self.write_to_file();
+        self.emit(Event::ItemMoved(index, new_position));
+        Ok(())
+    }
+
+    pub fn export(&self) -> Vec<u8> {
+        archive::export(&self.items)
+    }
+
+    /// Merging keeps the current items and sorts unknown ones in by their last copy, pins are
+    /// combined. Replacing discards the current history first.
+    /// Returns the amount of added items that are still present after eviction.
+    pub fn import(&mut self, data: &[u8], replace: bool) -> Result<u32, DaemonError> {
+        let imported = archive::import(data)?;
+        for (content, item) in &imported {
+            check_size(content)?;
+            for (_, variant) in &item.variants {
+                check_size(variant)?;
+            }
+        }
+        if replace {
+            self.items.clear();
+            self.emit(Event::HistoryCleared);
+        }
+        let mut added = HashSet::new();
+        for (content, item) in imported {
+            if let Some((index, _, existing)) = self.items.get_full_mut(&content) {
+                if item.pinned && !existing.pinned {
+                    existing.pinned = true;
+                    self.emit(Event::ItemChanged(index));
+                }
+                continue;
+            }
+            // newest first, items without timestamp end up last
+            let index = self
+                .items
+                .values()
+                .position(|existing| existing.copied < item.copied)
+                .unwrap_or(self.items.len());
+            let preview = entry_preview(&content, &item);
+            self.emit(Event::ItemAdded(index, item.mimetype.clone(), preview));
+            added.insert(hash_content(&content));
+            self.items.shift_insert(index, content, item);
+        }
+        self.summary_cache.prepare(&self.items);
+        self.evict();
+        self.write_to_file();
+        let present = self
+            .items
+            .keys()
+            .filter(|content| added.contains(&hash_content(content)))
+            .count();
+        Ok(present as u32)
+    }
+
+    /// Queued entries are pasted in order, every paste advances to the next entry.
+    /// Either all indexes are valid or nothing is queued.
+    pub fn queue_add(&mut self, indexes: &[usize]) -> Result<(), DaemonError> {
+        for index in indexes {
+            self.ensure_index(*index)?;
+        }
+        for index in indexes {
+            let (content, _) = self.items.get_index(*index).unwrap();
+            self.queue.entries.push_back(content.clone());
+        }
+        self.emit_queue_changed();
+        Ok(())
+    }
+
+    /// The next count captured copies are queued, serving starts after the last one.
+    pub fn queue_collect(&mut self, count: u32) {
+        self.queue.collecting = count;
+        self.emit_queue_changed();
+    }
+
+    /// Indexes of the queued entries, deleted items are skipped.
+    pub fn queue_indexes(&self) -> Vec<u32> {
+        self.queue
+            .entries
+            .iter()
+            .filter_map(|content| self.items.get_index_of(content))
+            .map(|index| index as u32)
+            .collect()
+    }
+
+    /// An entry that is currently served stays in the clipboard until it is pasted.
+    pub fn clear_queue(&mut self) {
+        self.queue.entries.clear();
+        self.queue.collecting = 0;
+        self.emit_queue_changed();
+    }
+
+    /// While the queue is served, the clipboard contains queued entries which are not captured.
+    pub fn queue_active(&self) -> bool {
+        self.queue.collecting == 0 && !self.queue.entries.is_empty()
+    }
+
+    /// Returns the entry to serve next, None if nothing is queued or an entry is still served.
+    pub fn next_queued(&mut self) -> Option<(Vec<u8>, String)> {
+        if self.queue.serving.is_some() || self.queue.collecting > 0 {
+            return None;
+        }
+        while let Some(content) = self.queue.entries.front() {
+            if let Some(item) = self.items.get(content) {
+                self.queue.serving = Some(content.clone());
+                return Some((content.clone(), item.mimetype.clone()));
+            }
+            self.queue.entries.pop_front();
+            self.emit_queue_changed();
+        }
+        None
+    }
+
+    /// Content copied while the daemon served the clipboard itself is captured afterwards.
+    /// Burned entries are only removed once they were pasted, undoing doesn't bring them back.
+    pub fn served(
+        &mut self,
+        outcome: ServeOutcome,
+        burned: Option<Vec<u8>>,
+    ) -> Result<(), DaemonError> {
+        match outcome? {
+            Some((contents, mimetype, variants)) => self.capture(contents, mimetype, variants),
+            None => {
+                if let Some(index) = burned.and_then(|content| self.items.get_index_of(&content)) {
+                    self.remove(index)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copying something else while the queue is served ends the queue, the new content is captured.
+    pub fn queue_served(&mut self, outcome: ServeOutcome) -> Result<(), DaemonError> {
+        let served = self.queue.serving.take();
+        match outcome {
+            Ok(None) => {
+                // the queue might have been cleared or refilled in the meantime
+                if served.is_some() && self.queue.entries.front() == served.as_ref() {
+                    self.queue.entries.pop_front();
+                    self.emit_queue_changed();
+                }
+                Ok(())
+            }
+            Ok(Some((contents, mimetype, variants))) => {
+                self.clear_queue();
+                self.capture(contents, mimetype, variants);
+                Ok(())
+            }
+            Err(error) => {
+                self.clear_queue();
+                Err(error)
+            }
+        }
+    }
+
+    /// Stores content in a register, e.g. the current clipboard.
+    pub fn set_register(
+        &mut self,
+        name: String,
+        content: Vec<u8>,
+        mimetype: String,
+    ) -> Result<(), DaemonError> {
+        if content.is_empty() {
+            return Err(DaemonError::InvalidArgument("Content is empty".into()));
+        }
+        check_size(&content)?;
+        let register = Register {
+            content,
+            mimetype,
+            stored: now(),
+        };
+        self.registers.set(name.clone(), register)?;
+        self.emit(Event::RegisterChanged(name));
+        Ok(())
+    }
+
+    /// The register keeps a copy, deleting the history entry afterwards does not affect it.
+    pub fn store_register(&mut self, name: String, index: usize) -> Result<(), DaemonError> {
+        let (content, mimetype) = self.content(index)?;
+        self.set_register(name, content, mimetype)
+    }
+
+    pub fn paste_register(&mut self, name: &str) -> Result<(), DaemonError> {
+        let register = self.registers.get(name)?;
+        let (content, mimetype) = (register.content.clone(), register.mimetype.clone());
+        self.copy_to_clipboard(content, mimetype)
+    }
+
+    pub fn register_content(&self, name: &str) -> Result<(Vec<u8>, String), DaemonError> {
+        let register = self.registers.get(name)?;
+        Ok((register.content.clone(), register.mimetype.clone()))
+    }
+
+    pub fn registers(&self) -> Vec<RegisterSummary> {
+        self.registers.summaries()
+    }
+
+    pub fn delete_register(&mut self, name: String) -> Result<(), DaemonError> {
+        self.registers.remove(&name)?;
+        self.emit(Event::RegisterChanged(name));
+        Ok(())
+    }
+
+    fn emit_queue_changed(&self) {
+        self.emit(Event::QueueChanged(self.queue_indexes().len()));
+    }
+
+    fn ensure_index(&self, index: usize) -> Result<(), DaemonError> {
+        if index < self.items.len() {
+            Ok(())
+        } else {
+            Err(DaemonError::invalid_index(index, self.items.len()))
+        }
+    }
+}
+
+fn items_file_path() -> PathBuf {
+    CONFIG_DIR.join("items")
+}
+
+fn ensure_items_file() -> PathBuf {
+    let item_file = items_file_path();
+    if !item_file.is_file() {
+        fs::File::create(&item_file).expect("Could not create item file.");
+    }
+    item_file
+}
+
+fn clear_items_file() {
+    let item_file = ensure_items_file();
+    let file = fs::File::options()
+        .write(true)
+        .append(false)
+        .open(&item_file)
+        .unwrap();
+    file.set_len(0).expect("Could not set size to 0");
+}
+
+/// Every path adding content applies the same limit as captures.
+fn check_size(content: &[u8]) -> Result<(), DaemonError> {
+    let max_size = config().max_item_size;
+    if content.len() > max_size {
+        return Err(DaemonError::content_too_large(max_size));
+    }
+    Ok(())
+}
+
+fn removed_event(index: usize, content: &[u8], item: &Item) -> Event {
+    Event::ItemRemoved(index, item.mimetype.clone(), entry_preview(content, item))
+}
+
+/// The query has to be lowercase already.
+/// Removes the oldest items above max_items, pinned ones neither count nor are removed.
+/// Returns the evicted items with their former indexes, later indexes first.
+/// This way the earlier ones stay valid for clients.
+fn evict_items(items: &mut IndexMap<Vec<u8>, Item>, max_items: usize) -> Deleted {
+    let evicted: Vec<usize> = items
+        .values()
+        .enumerate()
+        .filter(|(_, item)| !item.pinned)
+        .skip(max_items)
+        .map(|(index, _)| index)
+        .collect();
+    evicted
+        .into_iter()
+        .rev()
+        .filter_map(|index| {
+            let (content, item) = items.shift_remove_index(index)?;
+            Some((index, content, item))
+        })
+        .collect()
+}
+
+/// Removes all items, pinned ones are kept unless forced.
+/// Returns the removed items with their former indexes.
+fn clear_items(items: &mut IndexMap<Vec<u8>, Item>, force: bool) -> Deleted {
+    let mut deleted = Vec::new();
+    for (index, (content, item)) in std::mem::take(items).into_iter().enumerate() {
+        if item.pinned && !force {
+            items.insert(content, item);
+        } else {
+            deleted.push((index, content, item));
+        }
+    }
+    deleted
+}
+
+fn matches_query(content: &[u8], item: &Item, query: &str) -> bool {
+    item.mimetype.to_lowercase().contains(query)
+        || (!item.mimetype.starts_with("image/")
+            && String::from_utf8_lossy(content)
+                .to_lowercase()
+                .contains(query))
+}
+
+fn write_items_to_file(items: &IndexMap<Vec<u8>, Item>) {
+    let item_file = ensure_items_file();
+    // the whole history has to be written at once, otherwise only the last item remains
+    fs::write(&item_file, format_items(items)).expect("Could not write items file.");
+}
+
+fn get_items_from_file() -> IndexMap<Vec<u8>, Item> {
+    let item_file = ensure_items_file();
+    let mut buffer = String::from("");
+    let mut file = fs::File::open(&item_file).unwrap();
+    file.read_to_string(&mut buffer)
+        .expect("Could not read file");
+    parse_items(&buffer)
+}
+
+/// Every item is stored as content<>:<>mimetype<>:<>metadata<><>
+/// Binary content and content containing the separators is stored base64 encoded.
+fn format_items(items: &IndexMap<Vec<u8>, Item>) -> String {
+    let mut contents = String::new();
+    for (content, item) in items {
+        let mut metadata = item.metadata();
+        let content = match std::str::from_utf8(content) {
+            Ok(text) if !text.contains("<>") => text.to_string(),
+            _ => {
+                if !metadata.is_empty() {
+                    metadata.push(',');
+                }
+                metadata.push_str(BASE64_FLAG);
+                STANDARD.encode(content)
+            }
+        };
+        contents.push_str(&format!(
+            "{}<>:<>{}<>:<>{}<><>\n",
+            content, item.mimetype, metadata
+        ));
+    }
+    contents
+}
+
+fn parse_items(buffer: &str) -> IndexMap<Vec<u8>, Item> {
+    let mut items = IndexMap::new();
+    let lines: Vec<(&str, &str)> = buffer
+        .split("<><>\n")
+        .filter_map(|elem| elem.split_once("<>:<>"))
+        .collect();
+    for (key, value) in lines {
+        // older files do not contain any metadata
+        let (mimetype, metadata) = value.split_once("<>:<>").unwrap_or((value, ""));
+        let content = if metadata.split(',').any(|flag| flag == BASE64_FLAG) {
+            match STANDARD.decode(key) {
+                Ok(content) => content,
+                Err(_) => {
+                    eprintln!("Skipping an item with invalid base64 content");
+                    continue;
+                }
+            }
+        } else {
+            key.as_bytes().to_vec()
+        };
+        items.insert(content, Item::from_metadata(mimetype.to_string(), metadata));
+    }
+    items
+}
+
+/// Options shared by every clipboard write of the daemon.
+pub fn clipboard_options() -> Options {
+    let mut opts = Options::new();
+    opts.trim_newline(config().trim_newline);
+    opts.clipboard(wl_clipboard_rs::copy::ClipboardType::Regular);
+    opts
+}
+
+pub fn write_clipboard(
+    opts: Options,
+    content: Vec<u8>,
+    mimetype: String,
+) -> Result<(), DaemonError> {
+    let (source, mimetype) = clipboard_source(content, mimetype);
+    opts.copy(source, mimetype).map_err(copy_error)
+}
+
+pub fn clipboard_source(
+    content: Vec<u8>,
+    mimetype: String,
+) -> (Source, wl_clipboard_rs::copy::MimeType) {
+    let mimetype = match mimetype.as_str() {
+        "text/plain" => wl_clipboard_rs::copy::MimeType::Text,
+        _ => wl_clipboard_rs::copy::MimeType::Specific(mimetype),
+    };
+    (Source::Bytes(content.into()), mimetype)
+}
+
+pub fn copy_error(error: impl std::fmt::Display) -> DaemonError {
+    DaemonError::backend_unavailable(format!(
+        "Could not copy to clipboard! Make sure you have wl-clipboard installed. {}",
+        error
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_file_round_trip() {
+        let mut items = IndexMap::new();
+        items.insert(b"text".to_vec(), Item::new("text/plain".into()));
+        let mut image = Item::new("image/png".into());
+        image.pinned = true;
+        items.insert(vec![0x89, b'P', b'N', b'G', 0xff, 0x00], image);
+        items.insert(b"a<>:<>b<><>\n".to_vec(), Item::new("text/plain".into()));
+        items.insert(Vec::new(), Item::from_metadata("text/plain".into(), ""));
+
+        let parsed = parse_items(&format_items(&items));
+        assert_eq!(parsed.len(), items.len());
+        for ((content, item), (parsed_content, parsed_item)) in items.iter().zip(&parsed) {
+            assert_eq!(content, parsed_content);
+            assert_eq!(item.mimetype, parsed_item.mimetype);
+            assert_eq!(item.metadata(), parsed_item.metadata());
+        }
+    }
+
+    fn pinned_items(pinned: &[bool]) -> IndexMap<Vec<u8>, Item> {
+        pinned
+            .iter()
+            .enumerate()
+            .map(|(index, pinned)| {
+                let mut item = Item::new("text/plain".into());
+                item.pinned = *pinned;
+                (index.to_string().into_bytes(), item)
+            })
+            .collect()
+    }
+
+    fn keys(items: &IndexMap<Vec<u8>, Item>) -> Vec<&[u8]> {
+        items.keys().map(Vec::as_slice).collect()
+    }
+
+    #[test]
+    fn evicting_keeps_pinned_items() {
+        let mut items = pinned_items(&[true, false, false, true, false, false]);
+        let evicted = evict_items(&mut items, 2);
+        assert_eq!(keys(&items), [b"0", b"1", b"2", b"3"]);
+        let evicted: Vec<usize> = evicted.iter().map(|(index, _, _)| *index).collect();
+        assert_eq!(evicted, [5, 4]);
+
+        let evicted = evict_items(&mut items, 0);
+        assert_eq!(keys(&items), [b"0", b"3"]);
+        assert_eq!(evicted.len(), 2);
+        assert!(evict_items(&mut items, 0).is_empty());
+    }
+
+    #[test]
+    fn clearing_keeps_pinned_items() {
+        let mut items = pinned_items(&[false, true, false, true]);
+        let deleted = clear_items(&mut items, false);
+        assert_eq!(keys(&items), [b"1", b"3"]);
+        let deleted: Vec<usize> = deleted.iter().map(|(index, _, _)| *index).collect();
+        assert_eq!(deleted, [0, 2]);
+
+        assert_eq!(clear_items(&mut items, true).len(), 2);
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn items_file_of_older_versions() {
+        let items = parse_items("first<>:<>text/plain<><>\nsecond<>:<>text/html<>:<>pinned<><>\n");
+        assert_eq!(items.len(), 2);
+        let (content, item) = items.get_index(0).unwrap();
+        assert_eq!(content, b"first");
+        assert_eq!(item.mimetype, "text/plain");
+        assert!(!item.pinned);
+        let (content, item) = items.get_index(1).unwrap();
+        assert_eq!(content, b"second");
+        assert_eq!(item.mimetype, "text/html");
+        assert!(item.pinned);
+    }
+}
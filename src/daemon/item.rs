@@ -0,0 +1,139 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+/// Other mimetypes the content was offered as when it was copied, with their content.
+pub type Variants = Vec<(String, Vec<u8>)>;
+
+/// Metadata of a history entry, the content itself is the key of the history.
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub mimetype: String,
+    pub pinned: bool,
+    /// removed on its next paste, the clipboard is cleared once that paste was served
+    pub burn_after_reading: bool,
+    /// Unix timestamps in seconds, 0 for items stored before timestamps existed.
+    pub created: u64,
+    pub copied: u64,
+    pub variants: Variants,
+}
+
+impl Item {
+    pub fn new(mimetype: String) -> Self {
+        let now = now();
+        Self {
+            mimetype,
+            pinned: false,
+            burn_after_reading: false,
+            created: now,
+            copied: now,
+            variants: Vec::new(),
+        }
+    }
+
+    /// Metadata is stored as a comma separated list of flags in the items file.
+    /// Variants are stored as variant=mimetype:base64, their mimetypes contain neither , nor :.
+    pub fn from_metadata(mimetype: String, metadata: &str) -> Self {
+        let mut item = Self {
+            created: 0,
+            copied: 0,
+            ..Self::new(mimetype)
+        };
+        for flag in metadata.split(',') {
+            match flag.split_once('=') {
+                Some(("created", value)) => item.created = value.parse().unwrap_or(0),
+                Some(("copied", value)) => item.copied = value.parse().unwrap_or(0),
+                Some(("variant", value)) => {
+                    let variant = value.rsplit_once(':').and_then(|(mimetype, content)| {
+                        Some((mimetype.to_string(), STANDARD.decode(content).ok()?))
+                    });
+                    item.variants.extend(variant);
+                }
+                _ if flag == "pinned" => item.pinned = true,
+                _ if flag == "burn" => item.burn_after_reading = true,
+                _ => (),
+            }
+        }
+        item
+    }
+
+    pub fn metadata(&self) -> String {
+        let mut flags = Vec::new();
+        if self.pinned {
+            flags.push("pinned".to_string());
+        }
+        if self.burn_after_reading {
+            flags.push("burn".to_string());
+        }
+        if self.created != 0 {
+            flags.push(format!("created={}", self.created));
+        }
+        if self.copied != 0 {
+            flags.push(format!("copied={}", self.copied));
+        }
+        for (mimetype, content) in &self.variants {
+            flags.push(format!("variant={}:{}", mimetype, STANDARD.encode(content)));
+        }
+        flags.join(",")
+    }
+
+    /// Copying existing content again only refreshes the timestamp.
+    pub fn touch(&mut self) {
+        self.copied = now();
+    }
+}
+
+/// Compares mimetypes without parameters like charset.
+pub fn same_mimetype(a: &str, b: &str) -> bool {
+    let essence = |mimetype: &str| {
+        mimetype
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+    };
+    essence(a) == essence(b)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_round_trip() {
+        let item = Item {
+            mimetype: "text/plain".into(),
+            pinned: true,
+            burn_after_reading: true,
+            created: 10,
+            copied: 20,
+            variants: vec![("text/html".into(), b"<b>a,b:c</b>".to_vec())],
+        };
+        let parsed = Item::from_metadata(item.mimetype.clone(), &item.metadata());
+        assert!(parsed.pinned);
+        assert!(parsed.burn_after_reading);
+        assert_eq!(parsed.created, 10);
+        assert_eq!(parsed.copied, 20);
+        assert_eq!(parsed.variants, item.variants);
+    }
+
+    #[test]
+    fn metadata_of_older_versions() {
+        let item = Item::from_metadata("text/plain".into(), "");
+        assert!(!item.pinned);
+        assert!(!item.burn_after_reading);
+        assert_eq!((item.created, item.copied), (0, 0));
+
+        let item = Item::from_metadata("text/plain".into(), "pinned,created=5,unknown,copied=x");
+        assert!(item.pinned);
+        assert_eq!((item.created, item.copied), (5, 0));
+    }
+}
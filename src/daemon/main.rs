@@ -1,216 +1,448 @@
-use config::{default_config, Config, ConfigOptional};
-use iced::futures;
-use indexmap::IndexMap;
+use capture::{Capture, Captured};
+use config::{CONFIG_FILE, Config, ConfigOptional, default_config};
+use diagnostics::Diagnostics;
+use error::DaemonError;
+use history::History;
 use once_cell::sync::Lazy;
-use std::fs;
-use std::io::Read;
+use paste::{PasteOptions, ServeOutcome};
+use registers::RegisterSummary;
+use std::ops::ControlFlow;
 use std::path::PathBuf;
-use std::sync::mpsc;
+use std::sync::RwLock;
 use std::time::Duration;
-use wl_clipboard_rs::copy::{Options, Source};
-use wl_clipboard_rs::paste::{get_contents, ClipboardType, Error, MimeType, Seat};
+use summary::{Entry, Summary};
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{Instant, interval_at, sleep_until, timeout};
+use watcher::{WatcherState, WatcherStatus};
 
+pub mod archive;
+pub mod capture;
 pub mod config;
+#[path = "../common/config_watcher.rs"]
+pub mod config_watcher;
 pub mod dbus;
+pub mod diagnostics;
+pub mod error;
+pub mod history;
+pub mod item;
+pub mod paste;
+pub mod queue;
+pub mod registers;
+pub mod summary;
+pub mod watcher;
 // TODO wip
 //pub mod protocol;
 
-pub enum ReverseCommand {
-    SendLatest((Vec<u8>, String)),
-    SendAll(Vec<(Vec<u8>, String)>),
-}
+/// Every command is answered on its own channel,
+/// this ensures that concurrent clients never receive each others responses.
+pub type Reply<T> = oneshot::Sender<Result<T, DaemonError>>;
 
 pub enum Command {
-    WriteToFile,
-    ShutDown,
-    Copy,
-    DeleteAtIndex(usize),
-    DeleteAll,
-    GetLatest,
-    GetAll,
-    Paste(usize),
-    PasteAndDelete(usize),
+    ShutDown(Reply<()>),
+    Copy(Reply<()>),
+    /// Result of a clipboard read by a capture task, not sent by clients.
+    Captured(Result<Option<Captured>, DaemonError>, Reply<()>),
+    AddItem(Vec<u8>, String, bool, Reply<()>),
+    DeleteAtIndex(usize, Reply<()>),
+    DeleteAll(bool, Reply<()>),
+    RestorePrevious(Reply<u32>),
+    GetLatest(Reply<(Vec<u8>, String)>),
+    GetAll(Reply<Vec<(Vec<u8>, String)>>),
+    GetSummaries(Reply<Vec<Summary>>),
+    GetEntries(Option<String>, Reply<Vec<Entry>>),
+    GetEntry(usize, Reply<Entry>),
+    GetContent(usize, Reply<(Vec<u8>, String)>),
+    GetContentAs(usize, String, Reply<Vec<u8>>),
+    Paste(usize, Reply<()>),
+    PasteWithOptions(usize, PasteOptions, Reply<()>),
+    /// Sent by the task serving a paste with options, not by clients.
+    /// The reply is only included for foreground pastes, burned content is removed once pasted.
+    PasteServed(ServeOutcome, Option<Reply<()>>, Option<Vec<u8>>),
+    PasteAndDelete(usize, Reply<()>),
+    ReplaceContent(usize, Vec<u8>, String, Reply<()>),
+    Move(usize, usize, Reply<()>),
+    SetPinned(usize, bool, Reply<()>),
+    SetBurnAfterReading(usize, bool, Reply<()>),
+    ReloadConfig(Reply<()>),
+    SetPaused(bool, Reply<()>),
+    SetCollecting(bool, Reply<()>),
+    Export(Reply<Vec<u8>>),
+    Import(Vec<u8>, bool, Reply<u32>),
+    GetDiagnostics(Reply<Diagnostics>),
+    GetWatcherState(Reply<(String, String, u32)>),
+    QueueAdd(Vec<usize>, Reply<()>),
+    QueueCollect(u32, Reply<()>),
+    GetQueue(Reply<Vec<u32>>),
+    ClearQueue(Reply<()>),
+    SetRegister(String, Vec<u8>, String, Reply<()>),
+    StoreClipboardRegister(String, Reply<()>),
+    StoreRegister(String, usize, Reply<()>),
+    PasteRegister(String, Reply<()>),
+    GetRegisters(Reply<Vec<RegisterSummary>>),
+    GetRegisterContent(String, Reply<(Vec<u8>, String)>),
+    DeleteRegister(String, Reply<()>),
+    /// Sent by the task serving a queued entry, not by clients.
+    QueueServed(ServeOutcome),
 }
 
-static CONFIG_DIR: Lazy<PathBuf> = Lazy::new(|| oxilib::create_config_folder("oxipaste"));
+/// Changes of the history, these are sent as D-Bus signals.
+pub enum Event {
+    /// index, mimetype and preview
+    ItemAdded(usize, String, String),
+    ItemRemoved(usize, String, String),
+    ItemChanged(usize),
+    ItemMoved(usize, usize),
+    HistoryCleared,
+    PausedChanged(bool),
+    CollectingChanged(bool),
+    /// state name and details
+    WatcherStateChanged(&'static str, String),
+    /// remaining entries
+    QueueChanged(usize),
+    /// name of the stored or deleted register
+    RegisterChanged(String),
+}
 
-static CONFIG: Lazy<Config> = Lazy::new(|| {
-    oxilib::create_config::<Config, ConfigOptional>(&CONFIG_DIR, "config.toml", default_config())
-});
+const COMMAND_BUFFER: usize = 32;
+const WRITE_INTERVAL: Duration = Duration::from_secs(300);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
 
-fn main() {
-    std::thread::spawn(|| {
-        start_wl_copy_runner();
-    });
-    let (sender, receiver) = mpsc::channel::<Command>();
-    let (reverse_sender, reverse_receiver) = mpsc::channel::<ReverseCommand>();
-    std::thread::spawn(move || {
-        let _ = futures::executor::block_on(dbus::run(sender, reverse_receiver));
-    });
-    let mut items: IndexMap<Vec<u8>, String> = get_items_from_file();
-    let mut time = std::time::SystemTime::now();
-    loop {
-        let result = receiver.recv();
-        let len = items.len();
-        let new_time = std::time::SystemTime::now();
+static CONFIG_DIR: Lazy<PathBuf> = Lazy::new(|| oxilib::create_config_folder("oxipaste"));
 
-        if new_time
-            .duration_since(time)
-            .unwrap_or(Duration::from_millis(0))
-            > Duration::from_secs_f64(300.0)
-        {
-            write_items_to_file(&items);
-        }
-        time = new_time;
+static CONFIG: Lazy<RwLock<Config>> = Lazy::new(|| {
+    RwLock::new(oxilib::create_config::<Config, ConfigOptional>(
+        &CONFIG_DIR,
+        CONFIG_FILE,
+        default_config(),
+    ))
+});
 
-        // clean memory in order to not leak
-        // can later be configured with config file or something
-        if len > CONFIG.max_items {
-            let mut new_items = IndexMap::new();
-            let iter = items.into_iter();
-            for item in iter {
-                new_items.insert(item.0, item.1);
-            }
-            items = new_items;
-        }
-        if let Ok(command) = result {
-            match command {
-                Command::WriteToFile => {
-                    write_items_to_file(&items);
-                }
-                Command::ShutDown => {
-                    write_items_to_file(&items);
-                    break;
-                }
-                Command::Copy => get_items(&mut items),
-                Command::DeleteAtIndex(index) => {
-                    items.shift_remove_index(index);
-                }
-                Command::DeleteAll => {
-                    items.clear();
-                    clear_items_file();
-                }
-                Command::GetLatest => reverse_sender
-                    .send(ReverseCommand::SendLatest(paste_latest(&mut items)))
-                    .expect("Could not send command"),
-                Command::GetAll => reverse_sender
-                    .send(ReverseCommand::SendAll(items.clone().into_iter().collect()))
-                    .expect("Could not send command"),
-                Command::Paste(index) => copy_to_clipboard(&items, index),
-                Command::PasteAndDelete(index) => {
-                    copy_to_clipboard(&items, index);
-                    items.shift_remove_index(index);
-                }
-            }
-        }
-    }
+/// The config can be reloaded at any time, hence only a copy is handed out.
+pub fn config() -> Config {
+    CONFIG.read().unwrap().clone()
 }
 
-fn ensure_items_file() -> PathBuf {
-    let item_file = CONFIG_DIR.join("items");
-    if !item_file.is_file() {
-        fs::File::create(&item_file).expect("Could not create item file.");
-    }
-    item_file
+fn reload_config() -> Result<(), DaemonError> {
+    let config =
+        config::parse_config(&CONFIG_DIR.join(CONFIG_FILE)).map_err(DaemonError::InvalidConfig)?;
+    *CONFIG.write().unwrap() = config;
+    Ok(())
 }
 
-fn clear_items_file() {
-    let item_file = ensure_items_file();
-    let file = fs::File::options()
-        .write(true)
-        .append(false)
-        .open(&item_file)
-        .unwrap();
-    file.set_len(0).expect("Could not set size to 0");
+fn main() {
+    let runtime = Runtime::new().expect("Could not create the tokio runtime.");
+    runtime.block_on(daemon());
+    // served pastes block a thread until pasted, waiting for them would keep the daemon alive
+    runtime.shutdown_background();
 }
 
-fn write_items_to_file(items: &IndexMap<Vec<u8>, String>) {
-    let item_file = ensure_items_file();
-    for item in items {
-        let str_to_write_opt = String::from_utf8(item.0.to_vec());
-        if let Ok(str_to_write) = str_to_write_opt {
-            fs::write(&item_file, format!("{}<>:<>{}<><>\n", str_to_write, item.1))
-                .expect("Could not write default css content.");
+async fn daemon() {
+    let replace = std::env::args().skip(1).any(|arg| arg == "--replace");
+    let (sender, receiver) = mpsc::channel::<Command>(COMMAND_BUFFER);
+    let (event_sender, event_receiver) = mpsc::unbounded_channel::<Event>();
+    // a weak sender, the watcher should not keep the command loop alive
+    let reload_sender = sender.downgrade();
+    let capture_sender = sender.downgrade();
+    let conn = match dbus::connect(sender, replace).await {
+        Ok(conn) => conn,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
         }
+    };
+    // only the daemon owning the name may watch the clipboard
+    let (watcher_status, watcher_receiver) = watcher::status_channel();
+    let watcher = tokio::spawn(watcher::supervise(watcher_status, event_sender.clone()));
+    let config_watcher = config_watcher::watch_config(&CONFIG_DIR, CONFIG_FILE, move || {
+        if let Some(sender) = reload_sender.upgrade() {
+            let (reply, _) = oneshot::channel();
+            let _ = sender.try_send(Command::ReloadConfig(reply));
+        }
+    });
+    if let Err(error) = &config_watcher {
+        eprintln!("Could not watch config file: {}", error);
     }
+    let events_conn = conn.clone();
+    tokio::spawn(async move {
+        if let Err(error) = dbus::forward_events(events_conn, event_receiver).await {
+            eprintln!("Could not serve D-Bus interface: {}", error);
+        }
+    });
+    run(receiver, capture_sender, event_sender, watcher_receiver).await;
+    // dropping the supervisor kills wl-paste, it would otherwise outlive the daemon
+    watcher.abort();
+    let _ = watcher.await;
+    // pending replies like the one to ShutDown are sent before the connection is closed
+    let _ = timeout(SHUTDOWN_TIMEOUT, conn.graceful_shutdown()).await;
 }
 
-fn get_items_from_file() -> IndexMap<Vec<u8>, String> {
-    let mut items = IndexMap::new();
-    let item_file = ensure_items_file();
-    let mut buffer = String::from("");
-    let mut file = fs::File::open(&item_file).unwrap();
-    file.read_to_string(&mut buffer)
-        .expect("Could not read file");
-    let lines: Vec<(&str, &str)> = buffer
-        .split("<><>\n")
-        .filter_map(|elem| elem.split_once("<>:<>"))
-        .collect();
-    for (key, value) in lines {
-        items.insert(key.as_bytes().to_vec(), value.to_string());
-    }
-    items
-}
-
-fn copy_to_clipboard(items: &IndexMap<Vec<u8>, String>, index: usize) {
-    let item = items.get_index(index);
-    if item.is_none() {
-        eprintln!("Tried to access index {} which is none", index);
-        return;
-    }
-    let item = item.unwrap();
-
-    let mut opts = Options::new();
-    opts.trim_newline(true);
-    opts.clipboard(wl_clipboard_rs::copy::ClipboardType::Regular);
-    let res = opts.copy(
-        Source::Bytes(item.0.clone().into()),
-        match item.1.as_str() {
-            "text/plain" => wl_clipboard_rs::copy::MimeType::Text,
-            _ => wl_clipboard_rs::copy::MimeType::Specific(item.1.into()),
-        },
-    );
-    if res.is_err() {
-        eprintln!("Could not copy to clipboard! Make sure you have wl-clipboard installed.");
-    }
-}
-
-fn paste_latest(items: &mut IndexMap<Vec<u8>, String>) -> (Vec<u8>, String) {
-    if items.is_empty() {
-        return (Vec::new(), String::from("Empty"));
+async fn run(
+    mut receiver: mpsc::Receiver<Command>,
+    sender: mpsc::WeakSender<Command>,
+    events: mpsc::UnboundedSender<Event>,
+    watcher: watch::Receiver<WatcherStatus>,
+) {
+    let mut history = History::load(events);
+    let mut capture = Capture::new(sender.clone());
+    let mut write_interval = interval_at(Instant::now() + WRITE_INTERVAL, WRITE_INTERVAL);
+    loop {
+        let capture_deadline = capture.deadline();
+        tokio::select! {
+            command = receiver.recv() => {
+                // all senders are gone when the D-Bus interface stopped
+                let Some(command) = command else {
+                    history.write_to_file();
+                    break;
+                };
+                if handle_command(&mut history, &mut capture, &watcher, command).is_break() {
+                    break;
+                }
+                if let Some((content, mimetype)) = history.next_queued() {
+                    // the watcher reads every new selection once as well
+                    let watching = matches!(watcher.borrow().state, WatcherState::Running);
+                    paste::serve(
+                        sender.clone(),
+                        content,
+                        mimetype,
+                        history::clipboard_options(),
+                        Some(1 + watching as usize),
+                        None,
+                        Command::QueueServed,
+                    );
+                }
+            }
+            _ = sleep_until(capture_deadline.unwrap_or_else(Instant::now)),
+                if capture_deadline.is_some() => capture.start(),
+            _ = write_interval.tick() => history.write_to_file(),
+        }
     }
-    let last = items.last().unwrap();
-    (last.0.clone(), last.1.clone())
 }
 
-fn get_items(items: &mut IndexMap<Vec<u8>, String>) {
-    let result = get_contents(ClipboardType::Regular, Seat::Unspecified, MimeType::Any);
-    match result {
-        Ok((mut pipe, mimetype)) => {
-            let mut contents = vec![];
-            pipe.read_to_end(&mut contents)
-                .expect("Could not read from pipe");
-            if items.get(&contents).is_some() {
-                return;
+fn handle_command(
+    history: &mut History,
+    capture: &mut Capture,
+    watcher: &watch::Receiver<WatcherStatus>,
+    command: Command,
+) -> ControlFlow<()> {
+    match command {
+        Command::ShutDown(reply) => {
+            history.write_to_file();
+            let _ = reply.send(Ok(()));
+            return ControlFlow::Break(());
+        }
+        Command::Copy(reply) => {
+            // queued entries are already part of the history
+            if history.is_paused() || history.queue_active() || capture.is_serving() {
+                let _ = reply.send(Ok(()));
+                return ControlFlow::Continue(());
             }
-            items.shift_insert(0, contents, mimetype);
+            capture.request(reply);
         }
-
-        Err(Error::NoSeats) | Err(Error::ClipboardEmpty) | Err(Error::NoMimeType) => {
-            // not an error, just a non pipe state
+        Command::Captured(res, reply) => {
+            let res = res.map(|captured| {
+                if let Some((contents, mimetype, variants)) = captured {
+                    history.capture(contents, mimetype, variants);
+                }
+            });
+            let _ = reply.send(history.track(res));
+        }
+        Command::AddItem(content, mimetype, set_as_clipboard, reply) => {
+            let res = history.add(content, mimetype, set_as_clipboard);
+            let _ = reply.send(history.track(res));
+        }
+        Command::DeleteAtIndex(index, reply) => {
+            let _ = reply.send(history.delete(index));
+        }
+        Command::DeleteAll(force, reply) => {
+            history.clear(force);
+            let _ = reply.send(Ok(()));
+        }
+        // like pasting, burning entries are served once instead, they aren't moved as they are
+        // removed once pasted anyway
+        Command::RestorePrevious(reply) if history.restores_burning_entry() => {
+            let (served, result) = oneshot::channel();
+            serve_paste(history, capture, watcher, 1, PasteOptions::once(), served);
+            tokio::spawn(async move {
+                if let Ok(res) = result.await {
+                    let _ = reply.send(res.map(|()| 0));
+                }
+            });
+        }
+        Command::RestorePrevious(reply) => {
+            let res = history.restore_previous();
+            let _ = reply.send(history.track(res));
+        }
+        Command::GetLatest(reply) => {
+            let _ = reply.send(Ok(history.latest()));
+        }
+        Command::GetAll(reply) => {
+            let _ = reply.send(Ok(history.all()));
+        }
+        // thumbnails are created off the loop, the reply is sent once they exist
+        Command::GetSummaries(reply) => history.summaries(reply),
+        Command::GetEntries(query, reply) => {
+            let _ = reply.send(Ok(history.entries(query.as_deref())));
+        }
+        Command::GetEntry(index, reply) => {
+            let _ = reply.send(history.entry(index));
+        }
+        Command::GetContent(index, reply) => {
+            let _ = reply.send(history.content(index));
+        }
+        Command::GetContentAs(index, mimetype, reply) => {
+            let _ = reply.send(history.content_as(index, &mimetype));
+        }
+        // the entry is kept if pasting fails or something else is copied first
+        Command::Paste(index, reply) | Command::PasteAndDelete(index, reply)
+            if history.burns_after_reading(index) =>
+        {
+            serve_paste(
+                history,
+                capture,
+                watcher,
+                index,
+                PasteOptions::once(),
+                reply,
+            );
+        }
+        Command::Paste(index, reply) => {
+            let res = history.paste(index);
+            let _ = reply.send(history.track(res));
+        }
+        Command::PasteWithOptions(index, options, reply)
+            if options.is_served() || history.burns_after_reading(index) =>
+        {
+            serve_paste(history, capture, watcher, index, options, reply);
+        }
+        Command::PasteWithOptions(index, options, reply) => {
+            let res = history.content(index).and_then(|(content, mimetype)| {
+                history.write_clipboard(options.clipboard_options(), content, mimetype)
+            });
+            let _ = reply.send(history.track(res));
+        }
+        Command::PasteServed(outcome, reply, burned) => {
+            capture.served();
+            let res = history.served(outcome, burned);
+            let res = history.track(res);
+            if let Some(reply) = reply {
+                let _ = reply.send(res);
+            }
+        }
+        Command::PasteAndDelete(index, reply) => {
+            let res = history.paste_and_delete(index);
+            let _ = reply.send(history.track(res));
+        }
+        Command::ReplaceContent(index, content, mimetype, reply) => {
+            let _ = reply.send(history.replace(index, content, mimetype));
+        }
+        Command::Move(index, new_position, reply) => {
+            let _ = reply.send(history.move_item(index, new_position));
+        }
+        Command::SetPinned(index, pinned, reply) => {
+            let _ = reply.send(history.set_pinned(index, pinned));
+        }
+        Command::SetBurnAfterReading(index, burn, reply) => {
+            let _ = reply.send(history.set_burn_after_reading(index, burn));
+        }
+        Command::SetPaused(paused, reply) => {
+            history.set_paused(paused);
+            let _ = reply.send(Ok(()));
+        }
+        Command::SetCollecting(collecting, reply) => {
+            history.set_collecting(collecting);
+            let _ = reply.send(Ok(()));
+        }
+        Command::Export(reply) => {
+            let _ = reply.send(Ok(history.export()));
+        }
+        Command::Import(archive, replace, reply) => {
+            let _ = reply.send(history.import(&archive, replace));
+        }
+        Command::ReloadConfig(reply) => {
+            let res = reload_config();
+            match &res {
+                // a lower max_items applies immediately
+                Ok(()) => history.evict(),
+                Err(error) => eprintln!("Could not reload config: {}", error),
+            }
+            let _ = reply.send(history.track(res));
+        }
+        Command::GetDiagnostics(reply) => {
+            let _ = reply.send(Ok(history.diagnostics(&watcher.borrow())));
+        }
+        Command::GetWatcherState(reply) => {
+            let status = watcher.borrow();
+            let _ = reply.send(Ok((
+                status.state.name().into(),
+                status.state.detail(),
+                status.restarts,
+            )));
+        }
+        Command::QueueAdd(indexes, reply) => {
+            let _ = reply.send(history.queue_add(&indexes));
+        }
+        Command::QueueCollect(count, reply) => {
+            history.queue_collect(count);
+            let _ = reply.send(Ok(()));
+        }
+        Command::GetQueue(reply) => {
+            let _ = reply.send(Ok(history.queue_indexes()));
+        }
+        Command::ClearQueue(reply) => {
+            history.clear_queue();
+            let _ = reply.send(Ok(()));
+        }
+        Command::SetRegister(name, content, mimetype, reply) => {
+            let _ = reply.send(history.set_register(name, content, mimetype));
+        }
+        Command::StoreClipboardRegister(name, reply) => capture.read_into_register(name, reply),
+        Command::StoreRegister(name, index, reply) => {
+            let _ = reply.send(history.store_register(name, index));
+        }
+        Command::PasteRegister(name, reply) => {
+            let res = history.paste_register(&name);
+            let _ = reply.send(history.track(res));
+        }
+        Command::GetRegisters(reply) => {
+            let _ = reply.send(Ok(history.registers()));
+        }
+        Command::GetRegisterContent(name, reply) => {
+            let _ = reply.send(history.register_content(&name));
+        }
+        Command::DeleteRegister(name, reply) => {
+            let _ = reply.send(history.delete_register(name));
+        }
+        Command::QueueServed(outcome) => {
+            let res = history.queue_served(outcome);
+            let _ = history.track(res);
         }
-
-        Err(err) => eprintln!("{}", err),
     }
+    ControlFlow::Continue(())
 }
 
-fn start_wl_copy_runner() {
-    std::process::Command::new("wl-paste")
-        .args([
-            // "-p",
-            "-w",
-            "oxipaste_command_runner",
-        ])
-        .output()
-        .expect("Could not run command runner for wl-copy.");
+/// Pastes with paste-once or foreground are served by the daemon itself.
+fn serve_paste(
+    history: &History,
+    capture: &mut Capture,
+    watcher: &watch::Receiver<WatcherStatus>,
+    index: usize,
+    options: PasteOptions,
+    reply: Reply<()>,
+) {
+    match history.content(index) {
+        Ok((content, mimetype)) => {
+            let watching = matches!(watcher.borrow().state, WatcherState::Running);
+            // burning entries are always pasted once, whatever else was requested
+            let burn = history.burns_after_reading(index);
+            let options = PasteOptions {
+                paste_once: options.paste_once || burn,
+                ..options
+            };
+            capture.serve(content, mimetype, options, watching, burn, reply);
+        }
+        Err(error) => {
+            let _ = reply.send(Err(error));
+        }
+    }
 }
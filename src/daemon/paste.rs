@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::sync::mpsc::WeakSender;
+use tokio::task::spawn_blocking;
+use wl_clipboard_rs::copy::{Options, ServeRequests};
+use zbus::zvariant::OwnedValue;
+
+use crate::capture::{Captured, capture_clipboard};
+use crate::error::DaemonError;
+use crate::history::{clipboard_options, clipboard_source, copy_error};
+use crate::{Command, Reply, config};
+
+/// What the clipboard contains after the daemon stopped serving it.
+/// None means the content was pasted, otherwise another application took over the clipboard.
+pub type ServeOutcome = Result<Option<Captured>, DaemonError>;
+
+/// Options of a single paste, trim_newline falls back to the config.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PasteOptions {
+    pub trim_newline: Option<bool>,
+    /// the clipboard is cleared after the first paste
+    pub paste_once: bool,
+    /// the paste call only returns once the content was pasted or replaced
+    pub foreground: bool,
+}
+
+impl PasteOptions {
+    pub fn once() -> Self {
+        Self {
+            paste_once: true,
+            ..Self::default()
+        }
+    }
+
+    /// Parses the options of PasteWithOptions, unknown ones are rejected.
+    pub fn parse(options: HashMap<String, OwnedValue>) -> Result<Self, DaemonError> {
+        let mut parsed = Self::default();
+        for (key, value) in options {
+            let flag = bool::try_from(value).map_err(|_| {
+                DaemonError::InvalidArgument(format!("Paste option {} has to be a boolean", key))
+            })?;
+            match key.as_str() {
+                "trim-newline" => parsed.trim_newline = Some(flag),
+                "paste-once" => parsed.paste_once = flag,
+                "foreground" => parsed.foreground = flag,
+                _ => {
+                    return Err(DaemonError::InvalidArgument(format!(
+                        "Unknown paste option {}",
+                        key
+                    )));
+                }
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Other pastes are served in the background by wl-clipboard-rs.
+    pub fn is_served(&self) -> bool {
+        self.paste_once || self.foreground
+    }
+
+    pub fn clipboard_options(&self) -> Options {
+        let mut opts = clipboard_options();
+        if let Some(trim_newline) = self.trim_newline {
+            opts.trim_newline(trim_newline);
+        }
+        opts
+    }
+}
+
+/// Serves the content until it was requested often enough or the clipboard was taken over.
+/// Without a limit, only taking over the clipboard ends serving.
+/// started is answered as soon as the content is in the clipboard,
+/// done creates the command reporting the outcome to the command loop.
+pub fn serve(
+    sender: WeakSender<Command>,
+    content: Vec<u8>,
+    mimetype: String,
+    mut opts: Options,
+    requests: Option<usize>,
+    started: Option<Reply<()>>,
+    done: impl FnOnce(ServeOutcome) -> Command + Send + 'static,
+) {
+    let config = config();
+    opts.foreground(true);
+    if let Some(requests) = requests {
+        opts.serve_requests(ServeRequests::Only(requests));
+    }
+    tokio::spawn(async move {
+        let res = spawn_blocking(move || {
+            let (source, mimetype) = clipboard_source(content, mimetype);
+            match opts.prepare_copy(source, mimetype) {
+                Ok(prepared) => {
+                    if let Some(started) = started {
+                        let _ = started.send(Ok(()));
+                    }
+                    prepared.serve().map_err(copy_error)
+                }
+                Err(error) => {
+                    if let Some(started) = started {
+                        let _ = started.send(Err(copy_error(&error)));
+                    }
+                    Err(copy_error(error))
+                }
+            }
+        })
+        .await
+        .map_err(DaemonError::backend_unavailable)
+        .and_then(|res| res);
+        // a served source is destroyed which leaves the clipboard empty
+        let outcome = match res {
+            Ok(()) => {
+                capture_clipboard(
+                    config.max_item_size,
+                    Duration::from_millis(config.read_timeout_ms),
+                )
+                .await
+            }
+            Err(error) => Err(error),
+        };
+        if let Some(sender) = sender.upgrade() {
+            let _ = sender.send(done(outcome)).await;
+        }
+    });
+}
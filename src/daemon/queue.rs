@@ -0,0 +1,12 @@
+use std::collections::VecDeque;
+
+/// Entries pasted one after another, every paste advances the clipboard to the next one.
+/// Entries are stored by content, this way they survive changes of indexes.
+#[derive(Default)]
+pub struct PasteQueue {
+    pub entries: VecDeque<Vec<u8>>,
+    /// amount of upcoming copies that are added to the queue before serving starts
+    pub collecting: u32,
+    /// content currently in the clipboard, it is removed from the queue once pasted
+    pub serving: Option<Vec<u8>>,
+}
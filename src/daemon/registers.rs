@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+use crate::CONFIG_DIR;
+use crate::error::DaemonError;
+use crate::summary::create_preview;
+
+/// name, mimetype, size in bytes, text preview and the unix timestamp of storing it
+pub type RegisterSummary = (String, String, u64, String, u64);
+
+/// Named slots next to the history, they are never evicted.
+pub struct Registers {
+    registers: BTreeMap<String, Register>,
+}
+
+#[derive(Clone)]
+pub struct Register {
+    pub content: Vec<u8>,
+    pub mimetype: String,
+    pub stored: u64,
+}
+
+/// Registers may contain binary content as well, hence they are stored as JSON with base64 content.
+#[derive(Serialize, Deserialize)]
+struct StoredRegister {
+    mime: String,
+    content: String,
+    stored: u64,
+}
+
+impl Registers {
+    pub fn load() -> Self {
+        let registers = match fs::read(registers_file_path()) {
+            Ok(data) => parse_registers(&data).unwrap_or_else(|error| {
+                eprintln!("Could not read registers: {}", error);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self { registers }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Register, DaemonError> {
+        self.registers
+            .get(name)
+            .ok_or_else(|| DaemonError::UnknownRegister(format!("Register {} is empty", name)))
+    }
+
+    /// Overwrites the register if it exists already.
+    pub fn set(&mut self, name: String, register: Register) -> Result<(), DaemonError> {
+        validate_name(&name)?;
+        self.registers.insert(name, register);
+        self.write_to_file();
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), DaemonError> {
+        self.get(name)?;
+        self.registers.remove(name);
+        self.write_to_file();
+        Ok(())
+    }
+
+    pub fn summaries(&self) -> Vec<RegisterSummary> {
+        self.registers
+            .iter()
+            .map(|(name, register)| {
+                let preview = if register.mimetype.starts_with("image/") {
+                    String::new()
+                } else {
+                    create_preview(&register.content)
+                };
+                (
+                    name.clone(),
+                    register.mimetype.clone(),
+                    register.content.len() as u64,
+                    preview,
+                    register.stored,
+                )
+            })
+            .collect()
+    }
+
+    fn write_to_file(&self) {
+        let stored: BTreeMap<&String, StoredRegister> = self
+            .registers
+            .iter()
+            .map(|(name, register)| {
+                let stored = StoredRegister {
+                    mime: register.mimetype.clone(),
+                    content: STANDARD.encode(&register.content),
+                    stored: register.stored,
+                };
+                (name, stored)
+            })
+            .collect();
+        let data = serde_json::to_vec(&stored).expect("Could not serialize registers.");
+        fs::write(registers_file_path(), data).expect("Could not write registers file.");
+    }
+}
+
+fn registers_file_path() -> PathBuf {
+    CONFIG_DIR.join("registers.json")
+}
+
+fn parse_registers(data: &[u8]) -> Result<BTreeMap<String, Register>, String> {
+    let stored: BTreeMap<String, StoredRegister> =
+        serde_json::from_slice(data).map_err(|error| error.to_string())?;
+    stored
+        .into_iter()
+        .map(|(name, stored)| {
+            let content = STANDARD
+                .decode(&stored.content)
+                .map_err(|_| format!("content of register {} is not base64", name))?;
+            let register = Register {
+                content,
+                mimetype: stored.mime,
+                stored: stored.stored,
+            };
+            Ok((name, register))
+        })
+        .collect()
+}
+
+/// Names end up in command lines and dmenu listings, whitespace would make them ambiguous.
+fn validate_name(name: &str) -> Result<(), DaemonError> {
+    if name.is_empty()
+        || name
+            .chars()
+            .any(|char| char.is_whitespace() || char.is_control())
+    {
+        return Err(DaemonError::InvalidArgument(format!(
+            "Invalid register name {:?}, names must not be empty or contain whitespace",
+            name
+        )));
+    }
+    Ok(())
+}
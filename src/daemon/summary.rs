@@ -0,0 +1,156 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use image::ImageFormat;
+use indexmap::IndexMap;
+use tokio::task::spawn_blocking;
+
+use crate::Reply;
+use crate::item::Item;
+
+/// Maximum amount of characters sent as a text preview.
+pub const PREVIEW_LENGTH: usize = 200;
+/// Maximum width and height of image thumbnails.
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+/// index, mimetype, size in bytes, text preview, png thumbnail, pinned
+pub type Summary = (u32, String, u64, String, Vec<u8>, bool);
+
+/// index, mimetype, size in bytes, text preview, pinned, created and last copied unix timestamps
+pub type Entry = (u32, String, u64, String, bool, u64, u64);
+
+/// Thumbnails are expensive to create, hence they are cached by content hash.
+/// Missing ones are created on a blocking thread, the cache is shared with it.
+#[derive(Default)]
+pub struct SummaryCache {
+    thumbnails: Arc<Mutex<HashMap<u64, Vec<u8>>>>,
+}
+
+impl SummaryCache {
+    /// Summaries of all items, the reply is sent once all thumbnails exist.
+    pub fn summaries(&self, items: &IndexMap<Vec<u8>, Item>, reply: Reply<Vec<Summary>>) {
+        let mut thumbnails = self.thumbnails.lock().unwrap();
+        let mut hashes = HashSet::new();
+        let mut missing = Vec::new();
+        let mut summaries = Vec::new();
+        for (index, (content, item)) in items.iter().enumerate() {
+            let is_image = item.mimetype.starts_with("image/");
+            let hash = is_image.then(|| hash_content(content));
+            hashes.extend(hash);
+            let (preview, thumbnail) = match hash {
+                Some(hash) => {
+                    let thumbnail = thumbnails.get(&hash).cloned().unwrap_or_else(|| {
+                        missing.push((summaries.len(), hash, content.clone()));
+                        Vec::new()
+                    });
+                    (String::new(), thumbnail)
+                }
+                None => (create_preview(content), Vec::new()),
+            };
+            summaries.push((
+                index as u32,
+                item.mimetype.clone(),
+                content.len() as u64,
+                preview,
+                thumbnail,
+                item.pinned,
+            ));
+        }
+        // only keep thumbnails of items that still exist
+        thumbnails.retain(|hash, _| hashes.contains(hash));
+        drop(thumbnails);
+        if missing.is_empty() {
+            let _ = reply.send(Ok(summaries));
+            return;
+        }
+        let cache = self.thumbnails.clone();
+        spawn_blocking(move || {
+            for (position, hash, content) in missing {
+                let thumbnail = create_thumbnail(&content);
+                cache.lock().unwrap().insert(hash, thumbnail.clone());
+                summaries[position].4 = thumbnail;
+            }
+            let _ = reply.send(Ok(summaries));
+        });
+    }
+
+    /// Creates missing thumbnails in the background as soon as images enter the history.
+    /// Summaries then rarely have to wait for them.
+    pub fn prepare<'a>(&self, items: impl IntoIterator<Item = (&'a Vec<u8>, &'a Item)>) {
+        let thumbnails = self.thumbnails.lock().unwrap();
+        let missing: Vec<(u64, Vec<u8>)> = items
+            .into_iter()
+            .filter(|(_, item)| item.mimetype.starts_with("image/"))
+            .map(|(content, _)| (hash_content(content), content))
+            .filter(|(hash, _)| !thumbnails.contains_key(hash))
+            .map(|(hash, content)| (hash, content.clone()))
+            .collect();
+        drop(thumbnails);
+        if missing.is_empty() {
+            return;
+        }
+        let cache = self.thumbnails.clone();
+        spawn_blocking(move || {
+            for (hash, content) in missing {
+                let thumbnail = create_thumbnail(&content);
+                cache.lock().unwrap().insert(hash, thumbnail);
+            }
+        });
+    }
+}
+
+/// Entries are summaries for scripts, without thumbnails but with timestamps.
+pub fn create_entry(index: usize, content: &[u8], item: &Item) -> Entry {
+    (
+        index as u32,
+        item.mimetype.clone(),
+        content.len() as u64,
+        entry_preview(content, item),
+        item.pinned,
+        item.created,
+        item.copied,
+    )
+}
+
+pub fn entry_preview(content: &[u8], item: &Item) -> String {
+    if item.mimetype.starts_with("image/") {
+        String::new()
+    } else {
+        create_preview(content)
+    }
+}
+
+pub fn hash_content(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Binary content has no preview, D-Bus strings can't contain NUL either.
+pub fn create_preview(content: &[u8]) -> String {
+    let Ok(text) = std::str::from_utf8(content) else {
+        return String::new();
+    };
+    text.chars()
+        .filter(|char| *char != '\0')
+        .take(PREVIEW_LENGTH)
+        .collect()
+}
+
+/// Images that can't be decoded are sent as they are, the UI might still be able to show them.
+fn create_thumbnail(content: &[u8]) -> Vec<u8> {
+    let Ok(image) = image::load_from_memory(content) else {
+        return content.to_vec();
+    };
+    let mut thumbnail = Vec::new();
+    let res = image
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .write_to(&mut Cursor::new(&mut thumbnail), ImageFormat::Png);
+    if let Err(error) = res {
+        eprintln!("Could not create thumbnail: {}", error);
+        return content.to_vec();
+    }
+    thumbnail
+}
@@ -0,0 +1,138 @@
+use std::env;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::process::Command;
+use tokio::sync::{mpsc::UnboundedSender, watch};
+use tokio::time::{Instant, sleep};
+
+use crate::Event;
+
+const WATCHER: &str = "wl-paste";
+const RUNNER: &str = "oxipastectl";
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A watcher running this long is considered healthy again, which resets the backoff.
+const STABLE_RUNTIME: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+pub enum WatcherState {
+    Starting,
+    Running,
+    /// reason and seconds until the next attempt
+    Restarting(String, u64),
+    /// missing binary and seconds until the next attempt
+    MissingDependency(String, u64),
+}
+
+impl WatcherState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatcherState::Starting => "starting",
+            WatcherState::Running => "running",
+            WatcherState::Restarting(..) => "restarting",
+            WatcherState::MissingDependency(..) => "missing-dependency",
+        }
+    }
+
+    pub fn detail(&self) -> String {
+        match self {
+            WatcherState::Starting | WatcherState::Running => String::new(),
+            WatcherState::Restarting(reason, retry) => {
+                format!("{}, next attempt in {}s", reason, retry)
+            }
+            WatcherState::MissingDependency(binary, retry) => {
+                format!(
+                    "{} was not found in PATH, next attempt in {}s",
+                    binary, retry
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WatcherStatus {
+    pub state: WatcherState,
+    pub restarts: u32,
+}
+
+pub fn status_channel() -> (watch::Sender<WatcherStatus>, watch::Receiver<WatcherStatus>) {
+    watch::channel(WatcherStatus {
+        state: WatcherState::Starting,
+        restarts: 0,
+    })
+}
+
+/// Keeps wl-paste running, it calls oxipastectl on every clipboard change.
+/// The child is killed when this task is aborted.
+pub async fn supervise(status: watch::Sender<WatcherStatus>, events: UnboundedSender<Event>) {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut restarts = 0;
+    loop {
+        let state = match [WATCHER, RUNNER]
+            .into_iter()
+            .find(|binary| !in_path(binary))
+        {
+            Some(missing) => WatcherState::MissingDependency(missing.into(), backoff.as_secs()),
+            None => {
+                let started = Instant::now();
+                let reason = run_watcher(&status, &events, restarts).await;
+                if started.elapsed() >= STABLE_RUNTIME {
+                    backoff = INITIAL_BACKOFF;
+                }
+                restarts += 1;
+                WatcherState::Restarting(reason, backoff.as_secs())
+            }
+        };
+        update(&status, &events, state, restarts);
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Returns why the watcher stopped.
+async fn run_watcher(
+    status: &watch::Sender<WatcherStatus>,
+    events: &UnboundedSender<Event>,
+    restarts: u32,
+) -> String {
+    // -p would watch the primary selection instead
+    let child = Command::new(WATCHER)
+        .args(["-w", RUNNER, "copy"])
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(error) => return format!("Could not start {}: {}", WATCHER, error),
+    };
+    update(status, events, WatcherState::Running, restarts);
+    match child.wait().await {
+        Ok(exit) => format!("{} exited with {}", WATCHER, exit),
+        Err(error) => format!("Could not wait for {}: {}", WATCHER, error),
+    }
+}
+
+fn update(
+    status: &watch::Sender<WatcherStatus>,
+    events: &UnboundedSender<Event>,
+    state: WatcherState,
+    restarts: u32,
+) {
+    if !matches!(state, WatcherState::Running) {
+        eprintln!("Clipboard watcher {}: {}", state.name(), state.detail());
+    }
+    let _ = events.send(Event::WatcherStateChanged(state.name(), state.detail()));
+    status.send_replace(WatcherStatus { state, restarts });
+}
+
+fn in_path(binary: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| is_executable(&dir.join(binary))))
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
@@ -3,9 +3,12 @@ use std::{
     path::PathBuf,
 };
 
+use iced::futures::{self, SinkExt, Stream, StreamExt};
 use serde::Deserialize;
 
-use crate::{into_general_error, OxiPasteError};
+use crate::{config_watcher, into_general_error, Message, OxiPasteError};
+
+const CONFIG_FILE: &str = "config.toml";
 
 #[derive(Deserialize, Clone, Default, Debug)]
 #[allow(non_snake_case)]
@@ -84,7 +87,7 @@ pub fn create_config_dir() -> Result<PathBuf, Option<OxiPasteError>> {
 
 pub fn create_config() -> Result<PathBuf, Option<OxiPasteError>> {
     let config_dir = create_config_dir()?;
-    let config_file = config_dir.join("config.toml");
+    let config_file = config_dir.join(CONFIG_FILE);
     if !config_file.is_file() {
         let res = OpenOptions::new()
             .create_new(true)
@@ -99,3 +102,22 @@ pub fn create_config() -> Result<PathBuf, Option<OxiPasteError>> {
     }
     Ok(config_file)
 }
+
+/// Sends a message whenever the config file changes.
+pub fn watch_config() -> impl Stream<Item = Message> {
+    iced::stream::channel(10, |mut output| async move {
+        let (sender, mut receiver) = futures::channel::mpsc::unbounded();
+        let Ok(config_dir) = create_config_dir() else {
+            return;
+        };
+        // dropping the watcher stops watching
+        let Ok(_watcher) = config_watcher::watch_config(&config_dir, CONFIG_FILE, move || {
+            let _ = sender.unbounded_send(());
+        }) else {
+            return;
+        };
+        while receiver.next().await.is_some() {
+            let _ = output.send(Message::ReloadConfig).await;
+        }
+    })
+}
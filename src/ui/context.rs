@@ -14,7 +14,7 @@ use crate::{
     config::Config,
     copy_to_clipboard,
     custom_rich::CustomRich,
-    into_general_error,
+    get_text_content, into_daemon_error, into_general_error,
     utils::{mk_content_button, mk_svg},
 };
 
@@ -50,7 +50,7 @@ impl ImageContext {
         commands
             .into_iter()
             .map(|command| {
-                let res = ContextCommand::from_vec_and_value(command, "", true)?;
+                let res = ContextCommand::from_vec(command, true)?;
                 Ok(res)
             })
             .collect()
@@ -108,14 +108,11 @@ pub enum TextContext {
 }
 
 impl TextContext {
-    fn apply_value(
-        commands: Vec<Vec<String>>,
-        value: &str,
-    ) -> Vec<Result<ContextCommand, OxiPasteError>> {
+    fn apply_value(commands: Vec<Vec<String>>) -> Vec<Result<ContextCommand, OxiPasteError>> {
         commands
             .into_iter()
             .map(|command| {
-                let res = ContextCommand::from_vec_and_value(command, value, false)?;
+                let res = ContextCommand::from_vec(command, false)?;
                 Ok(res)
             })
             .collect()
@@ -154,12 +151,8 @@ impl TextContext {
         config: &Config,
     ) -> Vec<Result<ContextCommand, OxiPasteError>> {
         match self {
-            TextContext::Address(address) => {
-                Self::apply_value(config.AddressContextActions.clone(), &address.inner)
-            }
-            TextContext::Text(text) => {
-                Self::apply_value(config.PlainTextContextActions.clone(), text)
-            }
+            TextContext::Address(_) => Self::apply_value(config.AddressContextActions.clone()),
+            TextContext::Text(_) => Self::apply_value(config.PlainTextContextActions.clone()),
         }
     }
 }
@@ -170,6 +163,8 @@ pub enum ContentTypeId {
     AddressText,
     Image,
     All,
+    /// named registers of the daemon instead of the history
+    Registers,
 }
 
 impl Display for ContentTypeId {
@@ -179,6 +174,7 @@ impl Display for ContentTypeId {
             ContentTypeId::AddressText => "Addresses".into(),
             ContentTypeId::Image => "Images".into(),
             ContentTypeId::All => "All".into(),
+            ContentTypeId::Registers => "Registers".into(),
         };
         write!(f, "{}", text)
     }
@@ -223,6 +219,7 @@ impl ContentType {
 #[derive(Debug, Clone)]
 pub struct ContextMenu {
     pub(crate) toggled: bool,
+    pub(crate) pinned: bool,
     pub(crate) content_type: ContentType,
 }
 
@@ -240,11 +237,7 @@ pub struct ContextCommand {
 }
 
 impl ContextCommand {
-    pub fn from_vec_and_value(
-        mut args: Vec<String>,
-        value: &str,
-        requires_copy: bool,
-    ) -> Result<Self, OxiPasteError> {
+    pub fn from_vec(mut args: Vec<String>, requires_copy: bool) -> Result<Self, OxiPasteError> {
         if args.len() < 2 {
             return Err(OxiPasteError {
                 message: "Invalid Command: less than 2 arguments provided".into(),
@@ -252,17 +245,9 @@ impl ContextCommand {
         }
         let label = args.remove(0);
         let command = args.remove(0);
-        let mut found = false;
-        if !requires_copy {
-            for arg in args.iter_mut() {
-                if arg == "{}" {
-                    *arg = value.to_owned();
-                    found = true;
-                }
-            }
-            if !found {
-                args.push(value.to_owned());
-            }
+        // the placeholder is replaced with the full content when running the command
+        if !requires_copy && !args.iter().any(|arg| arg == "{}") {
+            args.push("{}".into());
         }
         Ok(Self {
             label,
@@ -273,14 +258,31 @@ impl ContextCommand {
     }
 
     pub fn run_command(&self, oxipaste: &OxiPaste, index: i32) -> Result<(), OxiPasteError> {
-        if self.requires_copy {
+        let args = if self.requires_copy {
             let res = futures::executor::block_on(copy_to_clipboard(&oxipaste.proxy, index as u32));
-            let err_opt = into_general_error(res.err());
+            let err_opt = into_daemon_error(res.err());
             if let Some(error) = err_opt {
                 return Err(error);
             }
-        }
-        let res = Command::new(&self.command).args(&self.args).spawn();
+            self.args.clone()
+        } else {
+            let res = futures::executor::block_on(get_text_content(&oxipaste.proxy, index as u32));
+            let value = match res {
+                Ok(value) => value,
+                Err(error) => return Err(into_daemon_error(Some(error)).unwrap()),
+            };
+            self.args
+                .iter()
+                .map(|arg| {
+                    if arg == "{}" {
+                        value.clone()
+                    } else {
+                        arg.clone()
+                    }
+                })
+                .collect()
+        };
+        let res = Command::new(&self.command).args(&args).spawn();
         if let Some(error) = into_general_error(res.err()) {
             Err(error)
         } else {
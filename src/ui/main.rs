@@ -1,7 +1,8 @@
+use std::collections::HashSet;
 use std::fmt::Display;
 use std::path::PathBuf;
 
-use config::{Config, create_config, default_config, parse_config};
+use config::{Config, create_config, default_config, parse_config, watch_config};
 use context::{
     Address, ContentType, ContentTypeId, ContextCommand, ContextMenu, ContextMenuMessage,
     GetContextActionsResult, ImageContext, TextContext,
@@ -23,10 +24,12 @@ use iced_layershell::Application;
 use iced_layershell::actions::LayershellCustomActions;
 use iced_layershell::reexport::{Anchor, KeyboardInteractivity, Layer};
 use iced_layershell::settings::{LayerShellSettings, Settings};
-use utils::{FocusDirection, mk_svg, svg_path};
+use utils::{FocusDirection, mk_content_button, mk_svg, svg_path};
 use zbus::{Connection, proxy};
 
 mod config;
+#[path = "../common/config_watcher.rs"]
+mod config_watcher;
 mod context;
 mod custom_rich;
 mod utils;
@@ -75,6 +78,42 @@ pub fn into_general_error(
     })
 }
 
+const DAEMON_ERROR_PREFIX: &str = "org.Xetibo.OxiPasteDaemon.Error.";
+/// Version of the daemon interface this UI was built against.
+const API_VERSION: u32 = 1;
+const REQUIRED_CAPABILITIES: [&str; 6] = [
+    "summaries",
+    "entries",
+    "pins",
+    "queue",
+    "registers",
+    "restore",
+];
+
+/// name, mimetype, size in bytes, text preview and the unix timestamp of storing it
+type RegisterSummary = (String, String, u64, String, u64);
+
+/// index, mimetype, size in bytes, text preview, png thumbnail, pinned
+type Summary = (u32, String, u64, String, Vec<u8>, bool);
+
+/// index, mimetype, size in bytes, text preview, pinned, created and last copied unix timestamps
+type Entry = (u32, String, u64, String, bool, u64, u64);
+
+/// Daemon errors are sent as org.Xetibo.OxiPasteDaemon.Error.*,
+/// only the error name and its description are relevant to the user.
+pub fn into_daemon_error(error_opt: Option<zbus::Error>) -> Option<OxiPasteError> {
+    let error = error_opt?;
+    let message = match &error {
+        zbus::Error::MethodError(name, detail, _) => format!(
+            "{}: {}",
+            name.as_str().trim_start_matches(DAEMON_ERROR_PREFIX),
+            detail.as_deref().unwrap_or("no details")
+        ),
+        _ => error.to_string(),
+    };
+    Some(OxiPasteError { message })
+}
+
 pub fn main() -> Result<(), iced_layershell::Error> {
     let settings = Settings {
         layer_settings: LayerShellSettings {
@@ -97,6 +136,9 @@ struct OxiPaste {
     filter_content_type: ContentTypeId,
     filtered_content: Vec<(i32, ContextMenu)>,
     clipboard_content: IndexMap<i32, ContextMenu>,
+    /// indexes in paste order
+    queue: Vec<i32>,
+    registers: Vec<RegisterSummary>,
     proxy: OxiPasteDbusProxy<'static>,
     errors: Vec<OxiPasteError>,
     config: Config,
@@ -107,14 +149,38 @@ impl Default for OxiPaste {
     fn default() -> Self {
         // when we don't have a proxy, we have other issues, aka goodbye
         let proxy = futures::executor::block_on(get_connection()).unwrap();
-        let data = futures::executor::block_on(get_items(&proxy));
         let mut errors = Vec::new();
-        let (clipboard_content, error_opt) = if let Ok(map) = data {
-            (map, None)
+        let (clipboard_content, error_opt) =
+            match futures::executor::block_on(check_compatibility(&proxy)) {
+                Ok(()) => {
+                    let data = futures::executor::block_on(get_items(&proxy));
+                    if let Ok(map) = data {
+                        (map, None)
+                    } else {
+                        (IndexMap::new(), into_daemon_error(data.err()))
+                    }
+                }
+                Err(error) => (IndexMap::new(), Some(error)),
+            };
+        error_opt.into_iter().for_each(|value| errors.push(value));
+        let (queue, registers) = if errors.is_empty() {
+            let queue = futures::executor::block_on(get_queue(&proxy)).unwrap_or_else(|error| {
+                into_daemon_error(Some(error))
+                    .into_iter()
+                    .for_each(|value| errors.push(value));
+                Vec::new()
+            });
+            let registers =
+                futures::executor::block_on(proxy.GetRegisters()).unwrap_or_else(|error| {
+                    into_daemon_error(Some(error))
+                        .into_iter()
+                        .for_each(|value| errors.push(value));
+                    Vec::new()
+                });
+            (queue, registers)
         } else {
-            (IndexMap::new(), into_general_error(data.err()))
+            (Vec::new(), Vec::new())
         };
-        error_opt.into_iter().for_each(|value| errors.push(value));
         let config_dir = create_config();
         let (config, error_opt) = if let Ok(dir) = config_dir {
             let config_res = parse_config(&dir);
@@ -127,21 +193,21 @@ impl Default for OxiPaste {
             (default_config(), config_dir.unwrap_err())
         };
         error_opt.into_iter().for_each(|value| errors.push(value));
-        let filtered_content = clipboard_content
-            .iter()
-            .map(|(key, value)| (*key, value.clone()))
-            .collect::<Vec<_>>();
-        Self {
+        let mut oxipaste = Self {
             theme: get_theme(),
             filter_text: "".into(),
             filter_content_type: ContentTypeId::All,
-            filtered_content,
+            filtered_content: Vec::new(),
             clipboard_content,
+            queue,
+            registers,
             proxy,
             errors,
             config,
             focus: 0,
-        }
+        };
+        oxipaste.filter();
+        oxipaste
     }
 }
 
@@ -149,15 +215,24 @@ impl Default for OxiPaste {
 enum Message {
     Copy(i32),
     CopyFromKeyboard(i32),
+    PasteAndDelete(i32),
     Remove(i32),
+    TogglePin(i32),
+    Enqueue(i32),
+    ClearQueue,
+    PasteRegister(String),
+    DeleteRegister(String),
     ClearClipboard,
+    RestorePrevious,
     SetFilterText(String),
     SetContentTypeFilter(ContentTypeId),
     RunContextCommand(ContextCommand, bool, i32),
     SubMessageContext(i32, ContextMenuMessage),
     MoveFocus(FocusDirection),
+    ReloadConfig,
     Exit,
     Enter,
+    EnterAndDelete,
 }
 
 impl TryInto<LayershellCustomActions> for Message {
@@ -192,21 +267,22 @@ fn wrap_in_rounded_box<'a>(
 impl OxiPaste {
     fn copy(&mut self, index: u32) -> Task<Message> {
         let res = futures::executor::block_on(copy_to_clipboard(&self.proxy, index));
-        into_general_error(res.err())
+        into_daemon_error(res.err())
             .into_iter()
             .for_each(|value| self.errors.push(value));
         // TODO make this work with iced exit?
-        exit(&self.config);
+        exit(&self.config, &self.errors);
         Task::none()
     }
 
     fn filter(&mut self) {
         // Reset the focus on filter
         self.focus = 0;
+        let matches = self.search();
         self.filtered_content = self
             .clipboard_content
             .iter()
-            .filter(|(_, value)| match &value.content_type {
+            .filter(|(key, value)| match &value.content_type {
                 ContentType::Text(text_content) => {
                     let (text, allow_type) = match text_content {
                         TextContext::Text(text) => (
@@ -220,9 +296,13 @@ impl OxiPaste {
                                 || self.filter_content_type == ContentTypeId::AddressText),
                         ),
                     };
-                    text.to_lowercase()
-                        .contains(&self.filter_text.to_lowercase())
-                        && allow_type
+                    matches.as_ref().map_or_else(
+                        || {
+                            text.to_lowercase()
+                                .contains(&self.filter_text.to_lowercase())
+                        },
+                        |matches| matches.contains(key),
+                    ) && allow_type
                 }
                 ContentType::Image(_) => {
                     (self.filter_text.contains("image") || self.filter_text.is_empty())
@@ -232,6 +312,46 @@ impl OxiPaste {
             })
             .map(|(key, value)| (*key, value.clone()))
             .collect::<Vec<(i32, ContextMenu)>>();
+        // registers are listed separately, see filtered_registers
+        // pinned items are shown in their own section at the top
+        self.filtered_content
+            .sort_by_key(|(_, value)| !value.pinned);
+    }
+
+    /// Previews are truncated, hence the daemon searches the full text instead.
+    /// None means that the previews are searched.
+    fn search(&mut self) -> Option<HashSet<i32>> {
+        if self.filter_text.is_empty() {
+            return None;
+        }
+        match futures::executor::block_on(self.proxy.SearchEntries(&self.filter_text)) {
+            Ok(entries) => Some(entries.into_iter().map(|entry| entry.0 as i32).collect()),
+            Err(error) => {
+                into_daemon_error(Some(error))
+                    .into_iter()
+                    .for_each(|value| self.errors.push(value));
+                None
+            }
+        }
+    }
+
+    fn filtered_registers(&self) -> Vec<&RegisterSummary> {
+        if self.filter_content_type != ContentTypeId::Registers {
+            return Vec::new();
+        }
+        let filter_text = self.filter_text.to_lowercase();
+        self.registers
+            .iter()
+            .filter(|(name, _, _, preview, _)| {
+                name.to_lowercase().contains(&filter_text)
+                    || preview.to_lowercase().contains(&filter_text)
+            })
+            .collect()
+    }
+
+    /// Amount of rows the keyboard focus can move over.
+    fn visible_rows(&self) -> usize {
+        self.filtered_content.len() + self.filtered_registers().len()
     }
 }
 
@@ -255,7 +375,7 @@ impl Application for OxiPaste {
     }
 
     fn subscription(&self) -> iced::Subscription<Self::Message> {
-        event::listen_with(|event, _status, _id| match event {
+        let keyboard = event::listen_with(|event, _status, _id| match event {
             iced::Event::Keyboard(iced::keyboard::Event::KeyPressed {
                 modifiers: modifier,
                 key: iced::keyboard::key::Key::Named(key),
@@ -265,6 +385,7 @@ impl Application for OxiPaste {
                 text: _,
             }) => match key {
                 Named::Escape => Some(Message::Exit),
+                Named::Enter if modifier.shift() => Some(Message::EnterAndDelete),
                 Named::Enter => Some(Message::Enter),
                 Named::Tab => match modifier {
                     Modifiers::SHIFT => Some(Message::MoveFocus(FocusDirection::Up)),
@@ -272,8 +393,16 @@ impl Application for OxiPaste {
                 },
                 _ => None,
             },
+            iced::Event::Keyboard(iced::keyboard::Event::KeyPressed {
+                modifiers: modifier,
+                key: iced::keyboard::key::Key::Character(character),
+                ..
+            }) if modifier.control() && character.as_str() == "z" => {
+                Some(Message::RestorePrevious)
+            }
             _ => None,
-        })
+        });
+        iced::Subscription::batch([keyboard, iced::Subscription::run(watch_config)])
     }
 
     fn update(&mut self, message: Message) -> Task<Message> {
@@ -290,6 +419,23 @@ impl Application for OxiPaste {
                 });
                 Task::none()
             }
+            Message::PasteAndDelete(index) => {
+                let res = futures::executor::block_on(self.proxy.PasteAndDelete(index as u32));
+                if let Some(error) = into_daemon_error(res.err()) {
+                    self.errors.push(error);
+                    return Task::none();
+                }
+                exit(&self.config, &self.errors);
+                // the indexes of all following entries changed, hence everything is fetched again
+                match futures::executor::block_on(get_items(&self.proxy)) {
+                    Ok(items) => self.clipboard_content = items,
+                    Err(error) => into_daemon_error(Some(error))
+                        .into_iter()
+                        .for_each(|value| self.errors.push(value)),
+                }
+                self.filter();
+                Task::none()
+            }
             Message::SetFilterText(value) => {
                 self.filter_text = value;
                 self.filter();
@@ -305,20 +451,92 @@ impl Application for OxiPaste {
                 self.filter();
                 Task::none()
             }
+            Message::TogglePin(index) => {
+                let context = self.clipboard_content.get_mut(&index).unwrap();
+                let res = futures::executor::block_on(set_pinned(
+                    &self.proxy,
+                    index as u32,
+                    !context.pinned,
+                ));
+                if let Some(error) = into_daemon_error(res.err()) {
+                    self.errors.push(error);
+                } else {
+                    context.pinned = !context.pinned;
+                    self.filter();
+                }
+                Task::none()
+            }
+            Message::Enqueue(index) => {
+                let res = futures::executor::block_on(self.proxy.QueueAdd(&[index as u32]));
+                if let Some(error) = into_daemon_error(res.err()) {
+                    self.errors.push(error);
+                } else {
+                    self.queue.push(index);
+                }
+                Task::none()
+            }
+            Message::ClearQueue => {
+                let res = futures::executor::block_on(self.proxy.ClearQueue());
+                if let Some(error) = into_daemon_error(res.err()) {
+                    self.errors.push(error);
+                } else {
+                    self.queue.clear();
+                }
+                Task::none()
+            }
+            Message::PasteRegister(name) => {
+                let res = futures::executor::block_on(self.proxy.PasteRegister(&name));
+                into_daemon_error(res.err())
+                    .into_iter()
+                    .for_each(|value| self.errors.push(value));
+                exit(&self.config, &self.errors);
+                Task::none()
+            }
+            Message::DeleteRegister(name) => {
+                let res = futures::executor::block_on(self.proxy.DeleteRegister(&name));
+                if let Some(error) = into_daemon_error(res.err()) {
+                    self.errors.push(error);
+                } else {
+                    self.registers
+                        .retain(|(register, _, _, _, _)| *register != name);
+                    self.focus = 0;
+                }
+                Task::none()
+            }
             Message::ClearClipboard => {
                 let res = futures::executor::block_on(delete_all(&self.proxy));
-                into_general_error(res.err())
+                into_daemon_error(res.err())
                     .into_iter()
                     .for_each(|value| self.errors.push(value));
                 // TODO make this work with iced exit?
-                exit(&self.config);
+                exit(&self.config, &self.errors);
+                Task::none()
+            }
+            Message::RestorePrevious => {
+                let res = futures::executor::block_on(self.proxy.RestorePrevious());
+                match res {
+                    // deleted entries are back, the window stays open to show them
+                    Ok(restored) if restored > 0 => {
+                        match futures::executor::block_on(get_items(&self.proxy)) {
+                            Ok(items) => self.clipboard_content = items,
+                            Err(error) => into_daemon_error(Some(error))
+                                .into_iter()
+                                .for_each(|value| self.errors.push(value)),
+                        }
+                        self.filter();
+                    }
+                    Ok(_) => exit(&self.config, &self.errors),
+                    Err(error) => into_daemon_error(Some(error))
+                        .into_iter()
+                        .for_each(|value| self.errors.push(value)),
+                }
                 Task::none()
             }
             Message::RunContextCommand(command, copy, index) => {
                 if copy {
                     let res =
                         futures::executor::block_on(copy_to_clipboard(&self.proxy, index as u32));
-                    into_general_error(res.err())
+                    into_daemon_error(res.err())
                         .into_iter()
                         .for_each(|value| self.errors.push(value));
                 }
@@ -326,7 +544,7 @@ impl Application for OxiPaste {
                 into_general_error(res.err())
                     .into_iter()
                     .for_each(|value| self.errors.push(value));
-                exit(&self.config);
+                exit(&self.config, &self.errors);
                 Task::none()
             }
             Message::SubMessageContext(index, ContextMenuMessage::Expand) => {
@@ -340,12 +558,36 @@ impl Application for OxiPaste {
                 std::process::exit(0);
             }
             Message::MoveFocus(focus_direction) => {
-                self.focus = focus_direction.add(self.focus, self.filtered_content.len());
+                self.focus = focus_direction.add(self.focus, self.visible_rows());
                 // TODO use when accessiblity is not complete ass
                 //iced::widget::focus_next()
                 Task::none()
             }
+            Message::Enter if self.filter_content_type == ContentTypeId::Registers => {
+                match self.filtered_registers().get(self.focus) {
+                    Some((name, _, _, _, _)) => Task::done(Message::PasteRegister(name.clone())),
+                    None => Task::none(),
+                }
+            }
             Message::Enter => Task::done(Message::CopyFromKeyboard(self.focus as i32)),
+            // registers are never deleted by pasting them
+            Message::EnterAndDelete if self.filter_content_type == ContentTypeId::Registers => {
+                Task::done(Message::Enter)
+            }
+            Message::EnterAndDelete => match self.filtered_content.get(self.focus) {
+                Some((key, _)) => Task::done(Message::PasteAndDelete(*key)),
+                None => Task::none(),
+            },
+            Message::ReloadConfig => {
+                // the previous config stays active when the new one is invalid
+                match create_config().and_then(|path| parse_config(&path)) {
+                    Ok(config) => self.config = config,
+                    Err(error_opt) => error_opt
+                        .into_iter()
+                        .for_each(|value| self.errors.push(value)),
+                }
+                Task::none()
+            }
         }
     }
 
@@ -407,6 +649,24 @@ fn clipboard_element<'a>(
             }))
             .spacing(20)
             .width(iced::Length::Fill),
+            button(
+                iced::widget::text(if context.pinned { "Unpin" } else { "Pin" }),
+                ButtonVariant::Primary
+            )
+            .on_press(Message::TogglePin(key))
+            .height(45),
+            button(
+                iced::widget::text(match state.queue.iter().position(|queued| *queued == key) {
+                    Some(position) => format!("Queued #{}", position + 1),
+                    None => "Queue".into(),
+                }),
+                ButtonVariant::Primary
+            )
+            .on_press(Message::Enqueue(key))
+            .height(45),
+            button("Paste & delete", ButtonVariant::Primary)
+                .on_press(Message::PasteAndDelete(key))
+                .height(45),
             button(
                 oxi_svg::svg_from_path(SvgStyleVariant::Primary, mk_svg("delete.svg")),
                 ButtonVariant::Primary
@@ -444,6 +704,11 @@ fn clipboard_element<'a>(
 }
 
 fn window(state: &OxiPaste) -> Column<Message> {
+    let pinned_count = state
+        .filtered_content
+        .iter()
+        .take_while(|(_, value)| value.pinned)
+        .count();
     let elements: Vec<Row<'_, Message>> = state
         .filtered_content
         .iter()
@@ -452,9 +717,17 @@ fn window(state: &OxiPaste) -> Column<Message> {
         .collect();
 
     let mut elements_col = column![];
-    for element in elements {
+    for (index, element) in elements.into_iter().enumerate() {
+        if pinned_count > 0 && index == 0 {
+            elements_col = elements_col.push(iced::widget::text("Pinned").size(20));
+        } else if pinned_count > 0 && index == pinned_count {
+            elements_col = elements_col.push(iced::widget::text("History").size(20));
+        }
         elements_col = elements_col.push_maybe(Some(element));
     }
+    for (index, register) in state.filtered_registers().into_iter().enumerate() {
+        elements_col = elements_col.push(register_element(index, register, state));
+    }
     let elements_scrollable = scrollable(elements_col);
 
     let mut col = Column::new()
@@ -466,15 +739,18 @@ fn window(state: &OxiPaste) -> Column<Message> {
                             ContentTypeId::All,
                             ContentTypeId::PlainText,
                             ContentTypeId::AddressText,
-                            ContentTypeId::Image
+                            ContentTypeId::Image,
+                            ContentTypeId::Registers
                         ],
                         Some(state.filter_content_type),
                         Message::SetContentTypeFilter
                     )
                     .width(Length::Fill),
+                    button("Undo", ButtonVariant::Primary).on_press(Message::RestorePrevious),
                     button("Clear all", ButtonVariant::Primary).on_press(Message::ClearClipboard)
                 ]
                 .spacing(10),
+                queue_indicator(state),
                 text_input(
                     "Enter text to find",
                     state.filter_text.as_str(),
@@ -498,6 +774,46 @@ fn window(state: &OxiPaste) -> Column<Message> {
     col
 }
 
+fn register_element<'a>(
+    index: usize,
+    (name, mimetype, _size, preview, _stored): &'a RegisterSummary,
+    state: &'a OxiPaste,
+) -> Row<'a, Message> {
+    let label = if mimetype.starts_with("image/") || preview.is_empty() {
+        format!("{}: {}", name, mimetype)
+    } else {
+        format!("{}: {}", name, preview)
+    };
+    row![
+        mk_content_button(state.focus, index, iced::widget::text(label).into())
+            .width(iced::Length::Fill)
+            .on_press(Message::PasteRegister(name.clone())),
+        button(
+            oxi_svg::svg_from_path(SvgStyleVariant::Primary, mk_svg("delete.svg")),
+            ButtonVariant::Primary
+        )
+        .on_press(Message::DeleteRegister(name.clone()))
+        .width(45)
+        .height(45),
+    ]
+    .padding(20)
+    .align_y(Alignment::Center)
+    .spacing(20)
+}
+
+/// The queue is only fetched when opening the window, pastes afterwards are not reflected.
+fn queue_indicator(state: &OxiPaste) -> Row<'_, Message> {
+    if state.queue.is_empty() {
+        return row![];
+    }
+    row![
+        iced::widget::text(format!("Queue: {} remaining", state.queue.len())).width(Length::Fill),
+        button("Clear queue", ButtonVariant::Primary).on_press(Message::ClearQueue)
+    ]
+    .align_y(Alignment::Center)
+    .spacing(10)
+}
+
 #[proxy(
     interface = "org.Xetibo.OxiPasteDaemon",
     default_service = "org.Xetibo.OxiPasteDaemon",
@@ -505,42 +821,100 @@ fn window(state: &OxiPaste) -> Column<Message> {
 )]
 #[allow(non_snake_case)]
 trait OxiPasteDbus {
-    async fn GetAll(&self) -> zbus::Result<Vec<(Vec<u8>, String)>>;
+    async fn ApiVersion(&self) -> zbus::Result<u32>;
+    async fn GetCapabilities(&self) -> zbus::Result<Vec<String>>;
+    async fn GetSummaries(&self) -> zbus::Result<Vec<Summary>>;
+    async fn GetContent(&self, index: u32) -> zbus::Result<(Vec<u8>, String)>;
+    async fn SearchEntries(&self, query: &str) -> zbus::Result<Vec<Entry>>;
     async fn Paste(&self, index: u32) -> zbus::Result<()>;
+    async fn PasteAndDelete(&self, index: u32) -> zbus::Result<()>;
     async fn DeleteAll(&self) -> zbus::Result<()>;
+    async fn RestorePrevious(&self) -> zbus::Result<u32>;
+    async fn Pin(&self, index: u32) -> zbus::Result<()>;
+    async fn Unpin(&self, index: u32) -> zbus::Result<()>;
+    async fn QueueAdd(&self, indexes: &[u32]) -> zbus::Result<()>;
+    async fn GetRegisters(&self) -> zbus::Result<Vec<RegisterSummary>>;
+    async fn PasteRegister(&self, name: &str) -> zbus::Result<()>;
+    async fn DeleteRegister(&self, name: &str) -> zbus::Result<()>;
+    async fn GetQueue(&self) -> zbus::Result<Vec<u32>>;
+    async fn ClearQueue(&self) -> zbus::Result<()>;
+}
+
+/// Ensures the running daemon supports everything this UI needs,
+/// instead of failing with cryptic errors on the first call.
+async fn check_compatibility(proxy: &OxiPasteDbusProxy<'static>) -> Result<(), OxiPasteError> {
+    let version = match proxy.ApiVersion().await {
+        Ok(version) => version,
+        Err(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "org.freedesktop.DBus.Error.ServiceUnknown" =>
+        {
+            return Err(OxiPasteError::new(
+                "oxipaste_daemon is not running, please start it first",
+            ));
+        }
+        Err(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "org.freedesktop.DBus.Error.UnknownMethod" =>
+        {
+            return Err(OxiPasteError::new(
+                "The running oxipaste_daemon is outdated, please restart it after updating",
+            ));
+        }
+        Err(error) => return Err(into_daemon_error(Some(error)).unwrap()),
+    };
+    if version != API_VERSION {
+        return Err(OxiPasteError {
+            message: format!(
+                "oxipaste_daemon provides API version {} but this UI requires version {}, please make sure both are updated and restart the daemon",
+                version, API_VERSION
+            ),
+        });
+    }
+    let capabilities = proxy
+        .GetCapabilities()
+        .await
+        .map_err(|error| into_daemon_error(Some(error)).unwrap())?;
+    let missing: Vec<&str> = REQUIRED_CAPABILITIES
+        .into_iter()
+        .filter(|capability| !capabilities.iter().any(|value| value == capability))
+        .collect();
+    if !missing.is_empty() {
+        return Err(OxiPasteError {
+            message: format!(
+                "The running oxipaste_daemon does not support: {}",
+                missing.join(", ")
+            ),
+        });
+    }
+    Ok(())
 }
 
 async fn get_items(proxy: &OxiPasteDbusProxy<'static>) -> zbus::Result<IndexMap<i32, ContextMenu>> {
-    let reply = proxy.GetAll().await?;
+    let reply = proxy.GetSummaries().await?;
 
     let mut map = IndexMap::new();
-    for item in reply {
-        if item.1.contains("text/plain") {
-            let item_res = String::from_utf8(item.0);
-            if item_res.is_err() {
-                return Err(zbus::Error::Failure(
-                    "Could not convert data from daemon".into(),
-                ));
-            }
-            let address_opt = Address::try_build(item_res.unwrap());
+    for (index, mimetype, _size, preview, thumbnail, pinned) in reply {
+        if mimetype.starts_with("image/") {
             map.insert(
-                map.len() as i32,
+                index as i32,
                 ContextMenu {
                     toggled: false,
-                    content_type: if let Ok(address) = address_opt {
-                        ContentType::Text(TextContext::Address(address))
-                    } else {
-                        // guaranteed error -> aka text, lmao
-                        ContentType::Text(TextContext::Text(address_opt.unwrap_err()))
-                    },
+                    pinned,
+                    content_type: ContentType::Image(ImageContext::Regular(thumbnail)),
                 },
             );
         } else {
+            let address_opt = Address::try_build(preview);
             map.insert(
-                map.len() as i32,
+                index as i32,
                 ContextMenu {
                     toggled: false,
-                    content_type: ContentType::Image(ImageContext::Regular(item.0)),
+                    pinned,
+                    content_type: if let Ok(address) = address_opt {
+                        ContentType::Text(TextContext::Address(address))
+                    } else {
+                        // guaranteed error -> aka text, lmao
+                        ContentType::Text(TextContext::Text(address_opt.unwrap_err()))
+                    },
                 },
             );
         }
@@ -548,11 +922,35 @@ async fn get_items(proxy: &OxiPasteDbusProxy<'static>) -> zbus::Result<IndexMap<
     Ok(map)
 }
 
+/// Previews are truncated, commands operating on text need the full content.
+async fn get_text_content(proxy: &OxiPasteDbusProxy<'static>, index: u32) -> zbus::Result<String> {
+    let (content, _mimetype) = proxy.GetContent(index).await?;
+    String::from_utf8(content)
+        .map_err(|_| zbus::Error::Failure("Could not convert data from daemon".into()))
+}
+
 async fn copy_to_clipboard(proxy: &OxiPasteDbusProxy<'static>, index: u32) -> zbus::Result<()> {
     proxy.Paste(index).await?;
     Ok(())
 }
 
+async fn set_pinned(
+    proxy: &OxiPasteDbusProxy<'static>,
+    index: u32,
+    pinned: bool,
+) -> zbus::Result<()> {
+    if pinned {
+        proxy.Pin(index).await
+    } else {
+        proxy.Unpin(index).await
+    }
+}
+
+async fn get_queue(proxy: &OxiPasteDbusProxy<'static>) -> zbus::Result<Vec<i32>> {
+    let queue = proxy.GetQueue().await?;
+    Ok(queue.into_iter().map(|index| index as i32).collect())
+}
+
 async fn delete_all(proxy: &OxiPasteDbusProxy<'static>) -> zbus::Result<()> {
     proxy.DeleteAll().await?;
     Ok(())
@@ -564,8 +962,9 @@ async fn get_connection() -> zbus::Result<OxiPasteDbusProxy<'static>> {
     Ok(proxy)
 }
 
-fn exit(config: &Config) {
-    if !config.keepOpen {
+/// Errors should stay visible, hence the window is only closed without errors.
+fn exit(config: &Config, errors: &[OxiPasteError]) {
+    if !config.keepOpen && errors.is_empty() {
         std::process::exit(0);
     }
 }